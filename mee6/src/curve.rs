@@ -0,0 +1,353 @@
+//! Level curves decide how much XP each level takes.
+use std::{
+    fmt::{Display, Formatter},
+    num::NonZeroU64,
+};
+
+/// A mapping from levels to the total XP needed to reach them.
+///
+/// Implementations must return `Some(0)` for level 0, and strictly increasing requirements
+/// after that. Levels which can't be reached, because the curve ends or the requirement
+/// doesn't fit in a `u64`, return `None`, and so must every level above them.
+/// [`LevelInfo`](crate::LevelInfo) relies on this to binary search for levels.
+pub trait LevelCurve {
+    /// Total XP needed to reach `level`, starting from nothing.
+    fn xp_needed_for_level(&self, level: u64) -> Option<u64>;
+}
+
+/// The curve MEE6 uses today, `6 + l^3.1155` rounded to a few significant digits, with an
+/// optional "secret level" XP wall.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Mee6 {
+    wall: Option<Wall>,
+}
+
+impl Mee6 {
+    #[must_use]
+    pub const fn new(wall: Option<Wall>) -> Self {
+        Self { wall }
+    }
+
+    #[must_use]
+    pub const fn wall(&self) -> Option<Wall> {
+        self.wall
+    }
+
+    #[allow(clippy::suboptimal_flops)]
+    fn polynomial(level: u64) -> Option<u64> {
+        if level == 0 {
+            return Some(0);
+        }
+        let xp = nice_round(6_f64 + (level as f64).powf(3.1155));
+        (xp < u64::MAX as f64).then_some(xp as u64)
+    }
+}
+
+impl Default for Mee6 {
+    /// MEE6's own curve, with the wall at level 30
+    fn default() -> Self {
+        Self::new(Some(Wall::MEE6))
+    }
+}
+
+impl LevelCurve for Mee6 {
+    fn xp_needed_for_level(&self, level: u64) -> Option<u64> {
+        match self.wall {
+            Some(wall) if level > wall.start => {
+                let wall_xp = Self::polynomial(wall.start)?;
+                let slope = wall.slope.map_or(wall_xp, NonZeroU64::get);
+                slope.checked_mul(level - wall.start)?.checked_add(wall_xp)
+            }
+            _ => Self::polynomial(level),
+        }
+    }
+}
+
+/// MEE6's "secret level" artificial XP wall. Past the `start` level, the curve stops
+/// getting steeper, and every level takes the same amount of XP.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Wall {
+    start: u64,
+    slope: Option<NonZeroU64>,
+}
+
+impl Wall {
+    /// The wall MEE6 uses, where every level past 30 takes as much XP as reaching level 30 did
+    pub const MEE6: Self = Self::new(30, None);
+
+    /// Each level past `start` takes `slope` XP, or as much XP as reaching `start` did if it's `None`.
+    #[must_use]
+    pub const fn new(start: u64, slope: Option<NonZeroU64>) -> Self {
+        Self { start, slope }
+    }
+
+    #[must_use]
+    pub const fn start(&self) -> u64 {
+        self.start
+    }
+
+    #[must_use]
+    pub const fn slope(&self) -> Option<NonZeroU64> {
+        self.slope
+    }
+}
+
+/// MEE6's original curve, where going from level `l` to `l + 1` takes `5l² + 50l + 100` XP.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Classic;
+
+impl LevelCurve for Classic {
+    fn xp_needed_for_level(&self, level: u64) -> Option<u64> {
+        // the sum of 5l² + 50l + 100 for every l below `level`
+        let level = u128::from(level);
+        let below = level.saturating_sub(1) * level;
+        let squares = below.checked_mul((2 * level).saturating_sub(1))? / 6;
+        let total = squares
+            .checked_mul(5)?
+            .checked_add(25 * below)?
+            .checked_add(100 * level)?;
+        u64::try_from(total).ok()
+    }
+}
+
+/// Every level takes the same amount of XP.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Linear {
+    xp_per_level: NonZeroU64,
+}
+
+impl Linear {
+    #[must_use]
+    pub const fn new(xp_per_level: NonZeroU64) -> Self {
+        Self { xp_per_level }
+    }
+
+    #[must_use]
+    pub const fn xp_per_level(&self) -> NonZeroU64 {
+        self.xp_per_level
+    }
+}
+
+impl LevelCurve for Linear {
+    fn xp_needed_for_level(&self, level: u64) -> Option<u64> {
+        level.checked_mul(self.xp_per_level.get())
+    }
+}
+
+/// Each level takes `growth` times as much XP as the one before it.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Exponential {
+    first_level: NonZeroU64,
+    growth: f64,
+}
+
+impl Exponential {
+    /// Returns `None` unless `growth` is a finite number above 1.
+    #[must_use]
+    pub fn new(first_level: NonZeroU64, growth: f64) -> Option<Self> {
+        (growth.is_finite() && growth > 1.0).then_some(Self {
+            first_level,
+            growth,
+        })
+    }
+
+    /// XP needed to go from level 0 to level 1
+    #[must_use]
+    pub const fn first_level(&self) -> NonZeroU64 {
+        self.first_level
+    }
+
+    #[must_use]
+    pub const fn growth(&self) -> f64 {
+        self.growth
+    }
+}
+
+impl LevelCurve for Exponential {
+    fn xp_needed_for_level(&self, level: u64) -> Option<u64> {
+        let level = i32::try_from(level).ok()?;
+        // the sum of a geometric series
+        let total =
+            self.first_level.get() as f64 * (self.growth.powi(level) - 1.0) / (self.growth - 1.0);
+        let total = total.round();
+        (total.is_finite() && total < u64::MAX as f64).then_some(total as u64)
+    }
+}
+
+/// An explicit list of the total XP needed for each level, starting at level 1.
+/// Levels past the end of the table can't be reached.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Table {
+    levels: Vec<u64>,
+}
+
+impl Table {
+    /// Returns `None` unless every level needs more XP than the one before it.
+    #[must_use]
+    pub fn new(levels: Vec<u64>) -> Option<Self> {
+        let mut previous = 0;
+        for &requirement in &levels {
+            if requirement <= previous {
+                return None;
+            }
+            previous = requirement;
+        }
+        Some(Self { levels })
+    }
+
+    #[must_use]
+    pub fn levels(&self) -> &[u64] {
+        &self.levels
+    }
+}
+
+impl LevelCurve for Table {
+    fn xp_needed_for_level(&self, level: u64) -> Option<u64> {
+        let Some(index) = level.checked_sub(1) else {
+            return Some(0);
+        };
+        self.levels.get(usize::try_from(index).ok()?).copied()
+    }
+}
+
+/// Any of the curves in this module, for when the curve is picked at runtime.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Curve {
+    Mee6(Mee6),
+    Classic,
+    Linear(Linear),
+    Exponential(Exponential),
+    Table(Table),
+}
+
+impl Default for Curve {
+    fn default() -> Self {
+        Self::Mee6(Mee6::default())
+    }
+}
+
+impl LevelCurve for Curve {
+    fn xp_needed_for_level(&self, level: u64) -> Option<u64> {
+        match self {
+            Self::Mee6(curve) => curve.xp_needed_for_level(level),
+            Self::Classic => Classic.xp_needed_for_level(level),
+            Self::Linear(curve) => curve.xp_needed_for_level(level),
+            Self::Exponential(curve) => curve.xp_needed_for_level(level),
+            Self::Table(curve) => curve.xp_needed_for_level(level),
+        }
+    }
+}
+
+impl Display for Curve {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mee6(curve) => match curve.wall {
+                Some(Wall::MEE6) => f.write_str("MEE6"),
+                Some(Wall {
+                    start,
+                    slope: Some(slope),
+                }) => write!(f, "MEE6, with {slope} XP per level after level {start}"),
+                Some(Wall { start, slope: None }) => {
+                    write!(f, "MEE6, with the XP wall at level {start}")
+                }
+                None => f.write_str("MEE6, without the XP wall"),
+            },
+            Self::Classic => f.write_str("classic MEE6"),
+            Self::Linear(curve) => write!(f, "linear, {} XP per level", curve.xp_per_level),
+            Self::Exponential(curve) => write!(
+                f,
+                "exponential, {} XP for level 1 growing {}x per level",
+                curve.first_level, curve.growth
+            ),
+            Self::Table(curve) => write!(f, "custom, {} levels", curve.levels.len()),
+        }
+    }
+}
+
+#[inline]
+#[must_use]
+fn nice_round(num: f64) -> f64 {
+    let multiple = (10_f64).powf((num.log10() / 2.0).floor());
+    (num / multiple).round() * multiple
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn non_zero(value: u64) -> NonZeroU64 {
+        NonZeroU64::new(value).unwrap()
+    }
+
+    #[test]
+    fn mee6_wall() {
+        let mee6 = Mee6::default();
+        assert_eq!(mee6.xp_needed_for_level(0), Some(0));
+        assert_eq!(mee6.xp_needed_for_level(30), Some(40_000));
+        assert_eq!(mee6.xp_needed_for_level(31), Some(80_000));
+        assert_eq!(mee6.xp_needed_for_level(u64::MAX), None);
+    }
+
+    #[test]
+    fn mee6_without_wall() {
+        let mee6 = Mee6::new(None);
+        assert_eq!(mee6.xp_needed_for_level(30), Some(40_000));
+        assert_eq!(mee6.xp_needed_for_level(31), Some(44_300));
+        let mut previous = 0;
+        for level in 1..10_000 {
+            let xp = mee6.xp_needed_for_level(level).unwrap();
+            assert!(xp > previous, "level {level} takes no XP");
+            previous = xp;
+        }
+        assert_eq!(mee6.xp_needed_for_level(u64::MAX), None);
+    }
+
+    #[test]
+    fn mee6_custom_wall() {
+        let mee6 = Mee6::new(Some(Wall::new(50, Some(non_zero(1000)))));
+        let level_50 = Mee6::new(None).xp_needed_for_level(50).unwrap();
+        assert_eq!(mee6.xp_needed_for_level(50), Some(level_50));
+        assert_eq!(mee6.xp_needed_for_level(53), Some(level_50 + 3000));
+        let mee6 = Mee6::new(Some(Wall::new(10, None)));
+        assert_eq!(mee6.xp_needed_for_level(12), Some(3 * 1310));
+    }
+
+    #[test]
+    fn classic() {
+        let mut total = 0;
+        for level in 0..1000 {
+            assert_eq!(Classic.xp_needed_for_level(level), Some(total));
+            total += 5 * level * level + 50 * level + 100;
+        }
+        assert_eq!(Classic.xp_needed_for_level(u64::MAX), None);
+    }
+
+    #[test]
+    fn linear() {
+        let curve = Linear::new(non_zero(100));
+        assert_eq!(curve.xp_needed_for_level(0), Some(0));
+        assert_eq!(curve.xp_needed_for_level(7), Some(700));
+        assert_eq!(curve.xp_needed_for_level(u64::MAX), None);
+    }
+
+    #[test]
+    fn exponential() {
+        assert!(Exponential::new(non_zero(100), 1.0).is_none());
+        assert!(Exponential::new(non_zero(100), f64::NAN).is_none());
+        let curve = Exponential::new(non_zero(100), 2.0).unwrap();
+        assert_eq!(curve.xp_needed_for_level(0), Some(0));
+        assert_eq!(curve.xp_needed_for_level(1), Some(100));
+        assert_eq!(curve.xp_needed_for_level(3), Some(700));
+        assert_eq!(curve.xp_needed_for_level(100), None);
+    }
+
+    #[test]
+    fn table() {
+        assert!(Table::new(vec![100, 100]).is_none());
+        assert!(Table::new(vec![0, 100]).is_none());
+        let curve = Table::new(vec![100, 250, 1000]).unwrap();
+        assert_eq!(curve.xp_needed_for_level(0), Some(0));
+        assert_eq!(curve.xp_needed_for_level(2), Some(250));
+        assert_eq!(curve.xp_needed_for_level(4), None);
+    }
+}
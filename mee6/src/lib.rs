@@ -6,7 +6,11 @@
     clippy::cast_possible_truncation
 )]
 //! A library to calculate mee6 levels.
-//! This can be calculated using the `LevelInfo` struct.
+//! This can be calculated using the `LevelInfo` struct, with any [`LevelCurve`].
+
+mod curve;
+
+pub use curve::{Classic, Curve, Exponential, LevelCurve, Linear, Mee6, Table, Wall};
 
 /// `LevelInfo` stores all of the data calculated when using `LevelInfo::new`(), so it can be cheaply
 /// gotten with getters.
@@ -14,30 +18,51 @@
 pub struct LevelInfo {
     xp: u64,
     level: u64,
+    next_level_xp: Option<u64>,
     percentage: f64,
 }
 
 impl LevelInfo {
     /// Create a new `LevelInfo` struct. This operation calculates the current percentage and level
     /// immediately, rather then when the getter is called.
+    ///
+    /// The level is found with a binary search, so this takes `O(log level)` calls to the curve.
+    /// Every `xp` up to and including `u64::MAX` is handled, and never overflows: members past
+    /// the last level a curve can represent just stay at that level.
     #[must_use]
-    pub fn new(xp: u64) -> Self {
-        let level = {
-            let mut testxp = 0;
-            let mut level = 0;
-            while xp >= testxp {
-                level += 1;
-                testxp = xp_needed_for_level(level);
-            }
-            level - 1
-        };
-        let last_level_xp_requirement = xp_needed_for_level(level);
-        let next_level_xp_requirement = xp_needed_for_level(level + 1);
+    pub fn new<C: LevelCurve + ?Sized>(curve: &C, xp: u64) -> Self {
+        let level = highest_level(curve, xp);
+        let last_level_xp_requirement = curve.xp_needed_for_level(level).unwrap_or(0);
+        Self::at_level(curve, xp, level, last_level_xp_requirement)
+    }
+
+    /// Create a `LevelInfo` for someone who has exactly enough xp to reach `level`.
+    /// Returns `None` if `level` can't be reached on this curve.
+    #[must_use]
+    pub fn from_level<C: LevelCurve + ?Sized>(curve: &C, level: u64) -> Option<Self> {
+        let xp = curve.xp_needed_for_level(level)?;
+        Some(Self::at_level(curve, xp, level, xp))
+    }
+
+    fn at_level<C: LevelCurve + ?Sized>(
+        curve: &C,
+        xp: u64,
+        level: u64,
+        last_level_xp_requirement: u64,
+    ) -> Self {
+        let next_level_xp = level
+            .checked_add(1)
+            .and_then(|next| curve.xp_needed_for_level(next));
+        // there is no next level, so the bar stays full
+        let percentage = next_level_xp.map_or(1.0, |next_level_xp_requirement| {
+            (xp as f64 - last_level_xp_requirement as f64)
+                / (next_level_xp_requirement as f64 - last_level_xp_requirement as f64)
+        });
         Self {
             xp,
             level,
-            percentage: ((xp as f64 - last_level_xp_requirement as f64)
-                / (next_level_xp_requirement as f64 - last_level_xp_requirement as f64)),
+            next_level_xp,
+            percentage,
         }
     }
 
@@ -55,6 +80,13 @@ impl LevelInfo {
         self.level
     }
 
+    /// Get the total xp needed to reach the next level, or `None` if this is the last level.
+    #[must_use]
+    #[inline]
+    pub const fn next_level_xp(&self) -> Option<u64> {
+        self.next_level_xp
+    }
+
     /// Get the percentage of the way this `LevelInfo` is to gaining a level, from the last level.
     #[must_use]
     #[inline]
@@ -64,24 +96,42 @@ impl LevelInfo {
     // mul_add is not no-std
 }
 
-#[allow(clippy::suboptimal_flops)]
-#[inline]
-#[must_use]
-pub fn xp_needed_for_level(level: u64) -> u64 {
-    // "secret level" feature (artificial xp wall)
-    if level > 30 {
-        return xp_needed_for_level(30) * (level - 29);
+/// The highest level someone with `xp` has reached on `curve`.
+fn highest_level<C: LevelCurve + ?Sized>(curve: &C, xp: u64) -> u64 {
+    let reached = |level| {
+        curve
+            .xp_needed_for_level(level)
+            .is_some_and(|needed| needed <= xp)
+    };
+    // level 0 is always reached. Double `high` until it isn't, so the level is in `low..high`
+    let mut low = 0;
+    let mut high = 1;
+    while reached(high) {
+        low = high;
+        high = match high.checked_mul(2) {
+            Some(high) => high,
+            None if reached(u64::MAX) => return u64::MAX,
+            None => u64::MAX,
+        };
     }
-
-    let base_xp = 6_f64 + (level as f64).powf(3.1155);
-    nice_round(base_xp) as u64
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if reached(mid) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    low
 }
 
+/// The total xp needed to reach `level` on the default [`Mee6`] curve.
 #[inline]
 #[must_use]
-fn nice_round(num: f64) -> f64 {
-    let multiple = (10_f64).powf((num.log10() / 2.0).floor());
-    (num / multiple).round() * multiple
+pub fn xp_needed_for_level(level: u64) -> u64 {
+    Mee6::default()
+        .xp_needed_for_level(level)
+        .unwrap_or(u64::MAX)
 }
 
 #[cfg(test)]
@@ -89,17 +139,110 @@ mod tests {
     use super::*;
     #[test]
     fn level() {
-        let inf = LevelInfo::new(3255);
+        let inf = LevelInfo::new(&Mee6::default(), 3255);
         assert_eq!(inf.level(), 13);
     }
     #[test]
     fn xp() {
-        let inf = LevelInfo::new(3255);
+        let inf = LevelInfo::new(&Mee6::default(), 3255);
         assert_eq!(inf.xp(), 3255);
     }
     #[test]
     fn percentage() {
-        let inf = LevelInfo::new(3255);
+        let inf = LevelInfo::new(&Mee6::default(), 3255);
         assert!((inf.percentage() - 0.38).abs() > f64::EPSILON);
     }
+    #[test]
+    fn curves() {
+        let curve = Curve::Linear(Linear::new(std::num::NonZeroU64::new(100).unwrap()));
+        let inf = LevelInfo::new(&curve, 250);
+        assert_eq!(inf.level(), 2);
+        assert_eq!(inf.next_level_xp(), Some(300));
+        assert!((inf.percentage() - 0.5).abs() < f64::EPSILON);
+    }
+    #[test]
+    fn last_level() {
+        let curve = Table::new(vec![100, 200]).unwrap();
+        let inf = LevelInfo::new(&curve, u64::MAX);
+        assert_eq!(inf.level(), 2);
+        assert_eq!(inf.next_level_xp(), None);
+        assert!((inf.percentage() - 1.0).abs() < f64::EPSILON);
+    }
+    fn linear_search<C: LevelCurve>(curve: &C, xp: u64) -> u64 {
+        let mut level = 0;
+        while curve
+            .xp_needed_for_level(level + 1)
+            .is_some_and(|needed| needed <= xp)
+        {
+            level += 1;
+        }
+        level
+    }
+
+    fn all_curves() -> Vec<Curve> {
+        let xp = std::num::NonZeroU64::new(100).unwrap();
+        vec![
+            Curve::default(),
+            Curve::Mee6(Mee6::new(None)),
+            Curve::Mee6(Mee6::new(Some(Wall::new(5, Some(xp))))),
+            Curve::Classic,
+            Curve::Linear(Linear::new(xp)),
+            Curve::Linear(Linear::new(std::num::NonZeroU64::MIN)),
+            Curve::Exponential(Exponential::new(xp, 1.1).unwrap()),
+            Curve::Table(Table::new(vec![100, 200, 5000]).unwrap()),
+        ]
+    }
+
+    #[test]
+    fn binary_search_matches_linear() {
+        for curve in all_curves() {
+            for xp in (0..20_000)
+                .step_by(7)
+                .chain([99, 100, 101, 199, 200, 4999, 5000])
+            {
+                assert_eq!(
+                    LevelInfo::new(&curve, xp).level(),
+                    linear_search(&curve, xp),
+                    "{curve:?} at {xp} xp"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn huge_xp() {
+        for curve in all_curves() {
+            for xp in (0..64)
+                .map(|shift| 1 << shift)
+                .chain([u64::MAX - 1, u64::MAX])
+            {
+                let inf = LevelInfo::new(&curve, xp);
+                let needed = curve.xp_needed_for_level(inf.level()).unwrap();
+                assert!(needed <= xp, "{curve:?} at {xp} xp");
+                assert!(
+                    inf.next_level_xp().is_none_or(|next| next > xp),
+                    "{curve:?} at {xp} xp"
+                );
+            }
+        }
+        let inf = LevelInfo::new(&Linear::new(std::num::NonZeroU64::MIN), u64::MAX);
+        assert_eq!(inf.level(), u64::MAX);
+        assert_eq!(inf.next_level_xp(), None);
+    }
+
+    #[test]
+    fn from_level() {
+        for curve in all_curves() {
+            for level in 0..3 {
+                let inf = LevelInfo::from_level(&curve, level).unwrap();
+                assert_eq!(inf.level(), level);
+                assert_eq!(LevelInfo::new(&curve, inf.xp()), inf);
+            }
+        }
+        assert_eq!(LevelInfo::from_level(&Mee6::default(), u64::MAX), None);
+        assert_eq!(
+            LevelInfo::from_level(&Table::new(vec![100]).unwrap(), 2),
+            None
+        );
+    }
 }
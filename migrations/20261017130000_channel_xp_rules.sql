@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE channel_xp_rules (
+    id BIGINT NOT NULL,
+    guild BIGINT NOT NULL,
+    multiplier FLOAT4 NOT NULL,
+    PRIMARY KEY (id, guild)
+);
+
+CREATE INDEX ON channel_xp_rules USING HASH (guild);
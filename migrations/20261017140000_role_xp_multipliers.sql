@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE role_xp_multipliers (
+    id BIGINT NOT NULL,
+    guild BIGINT NOT NULL,
+    multiplier FLOAT4 NOT NULL,
+    PRIMARY KEY (id, guild)
+);
+
+CREATE INDEX ON role_xp_multipliers USING HASH (guild);
@@ -0,0 +1,19 @@
+-- Add migration script here
+CREATE TABLE rank_rewards (
+    id BIGINT NOT NULL,
+    guild BIGINT NOT NULL,
+    max_rank BIGINT NOT NULL,
+    PRIMARY KEY (id, guild)
+);
+
+CREATE INDEX ON rank_rewards USING HASH (guild);
+
+-- Who we last gave each rank reward to, so we know who to take it away from.
+-- Holders of a removed rank reward still have its role, so this doesn't reference rank_rewards,
+-- and they're kept around until the rank rewards job has taken it away.
+CREATE TABLE rank_reward_holders (
+    role BIGINT NOT NULL,
+    guild BIGINT NOT NULL,
+    id BIGINT NOT NULL,
+    PRIMARY KEY (role, guild, id)
+);
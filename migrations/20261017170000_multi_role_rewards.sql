@@ -0,0 +1,8 @@
+-- Add migration script here
+-- Levels can now have several rewards, and rewards can take roles away as well as give them.
+-- Each role can be given at one level, and taken away at one level.
+ALTER TABLE role_rewards
+    DROP CONSTRAINT role_rewards_guild_requirement_key,
+    DROP CONSTRAINT role_rewards_guild_id_key,
+    ADD COLUMN remove BOOLEAN NOT NULL DEFAULT false,
+    ADD UNIQUE (guild, id, remove);
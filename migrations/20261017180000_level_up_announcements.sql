@@ -0,0 +1,18 @@
+-- Add migration script here
+ALTER TABLE guild_configs
+    ADD COLUMN level_up_embed BOOLEAN,
+    ADD COLUMN level_up_embed_title VARCHAR(256),
+    ADD COLUMN level_up_embed_color INT4,
+    ADD COLUMN level_up_embed_thumbnail BOOLEAN;
+
+-- Extra level-up messages. Ones with a level replace the normal message at that level,
+-- and ones without are picked at random alongside the normal message.
+CREATE TABLE level_up_messages (
+    id BIGSERIAL PRIMARY KEY,
+    guild BIGINT NOT NULL REFERENCES guild_configs (id) ON DELETE CASCADE,
+    level BIGINT,
+    message VARCHAR(512) NOT NULL
+);
+
+CREATE UNIQUE INDEX ON level_up_messages (guild, level) WHERE level IS NOT NULL;
+CREATE INDEX ON level_up_messages USING HASH (guild);
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- NULL or 0 is the MEE6 curve, 1 is classic MEE6, 2 is linear, 3 is exponential and 4 is a table.
+-- level_curve_xp is the XP per level of linear curves, and of the first level of exponential ones.
+ALTER TABLE guild_configs
+    ADD COLUMN level_curve INT2,
+    ADD COLUMN level_curve_xp INT8,
+    ADD COLUMN level_curve_growth FLOAT8,
+    ADD COLUMN level_curve_table INT8[];
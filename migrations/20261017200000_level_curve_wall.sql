@@ -0,0 +1,8 @@
+-- Add migration script here
+-- The "secret level" XP wall of MEE6 curves. NULL level_curve_wall means the wall is on,
+-- NULL level_curve_wall_start means level 30, and NULL level_curve_wall_slope means each
+-- level past the wall takes as much XP as reaching the wall did.
+ALTER TABLE guild_configs
+    ADD COLUMN level_curve_wall BOOLEAN,
+    ADD COLUMN level_curve_wall_start INT8,
+    ADD COLUMN level_curve_wall_slope INT8;
@@ -0,0 +1,18 @@
+-- Add migration script here
+ALTER TABLE levels
+    ADD COLUMN prestige INT8 NOT NULL DEFAULT 0;
+
+ALTER TABLE guild_configs
+    ADD COLUMN prestige BOOLEAN,
+    ADD COLUMN prestige_level INT8,
+    ADD COLUMN prestige_xp_multiplier FLOAT4;
+
+-- Roles given to members once they reach a prestige tier, kept through later prestiges
+CREATE TABLE prestige_rewards (
+    id BIGINT NOT NULL,
+    guild BIGINT NOT NULL,
+    tier BIGINT NOT NULL,
+    PRIMARY KEY (id, guild)
+);
+
+CREATE INDEX ON prestige_rewards USING HASH (guild);
@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE message_cooldowns (
+    guild INT8 NOT NULL,
+    id INT8 NOT NULL,
+    -- seconds since the Discord epoch, like snowflake timestamps
+    expires INT8 NOT NULL,
+    PRIMARY KEY (guild, id)
+);
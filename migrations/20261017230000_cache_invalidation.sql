@@ -0,0 +1,40 @@
+-- Add migration script here
+-- Tells every listener which guild's cached data changed, as `<cache>:<guild id>`.
+-- The first trigger argument is the cache name, the second is the column holding the guild ID.
+CREATE FUNCTION notify_cache_invalidation() RETURNS trigger AS $$
+DECLARE
+    changed JSONB;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        changed := to_jsonb(OLD);
+    ELSE
+        changed := to_jsonb(NEW);
+    END IF;
+    PERFORM pg_notify('xpd_cache_invalidation', TG_ARGV[0] || ':' || (changed ->> TG_ARGV[1]));
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER guild_configs_cache_invalidation
+    AFTER INSERT OR UPDATE OR DELETE ON guild_configs
+    FOR EACH ROW EXECUTE FUNCTION notify_cache_invalidation('config', 'id');
+
+CREATE TRIGGER role_rewards_cache_invalidation
+    AFTER INSERT OR UPDATE OR DELETE ON role_rewards
+    FOR EACH ROW EXECUTE FUNCTION notify_cache_invalidation('rewards', 'guild');
+
+CREATE TRIGGER prestige_rewards_cache_invalidation
+    AFTER INSERT OR UPDATE OR DELETE ON prestige_rewards
+    FOR EACH ROW EXECUTE FUNCTION notify_cache_invalidation('rewards', 'guild');
+
+CREATE TRIGGER channel_xp_rules_cache_invalidation
+    AFTER INSERT OR UPDATE OR DELETE ON channel_xp_rules
+    FOR EACH ROW EXECUTE FUNCTION notify_cache_invalidation('channel_rules', 'guild');
+
+CREATE TRIGGER role_xp_multipliers_cache_invalidation
+    AFTER INSERT OR UPDATE OR DELETE ON role_xp_multipliers
+    FOR EACH ROW EXECUTE FUNCTION notify_cache_invalidation('role_rules', 'guild');
+
+CREATE TRIGGER level_up_messages_cache_invalidation
+    AFTER INSERT OR UPDATE OR DELETE ON level_up_messages
+    FOR EACH ROW EXECUTE FUNCTION notify_cache_invalidation('level_up_messages', 'guild');
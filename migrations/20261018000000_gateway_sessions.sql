@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE gateway_sessions (
+    shard INT8 NOT NULL PRIMARY KEY,
+    total INT8 NOT NULL,
+    session_id TEXT NOT NULL,
+    sequence INT8 NOT NULL,
+    saved_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
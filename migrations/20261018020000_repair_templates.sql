@@ -0,0 +1,45 @@
+-- Add migration script here
+-- Templates used to be allowed to end on a `\`, or on a `{name` with no closing `}`, which no longer compiles.
+-- The old compiler dropped a trailing `\` and read a trailing `{name` as the variable `name`,
+-- so strip the `\` and close the tag, which keeps them rendering the same as they did before.
+CREATE FUNCTION repair_template(template TEXT) RETURNS TEXT AS $$
+DECLARE
+    repaired TEXT := '';
+    i INT := 1;
+    ch TEXT;
+    open_tag BOOLEAN := FALSE;
+BEGIN
+    WHILE i <= length(template) LOOP
+        ch := substr(template, i, 1);
+        IF ch = '\' THEN
+            -- keep escapes as they are, and drop a trailing `\` which has nothing to escape
+            repaired := repaired || substr(template, i, 2);
+            IF i = length(template) THEN
+                repaired := left(repaired, -1);
+            END IF;
+            i := i + 2;
+        ELSE
+            IF ch = '{' THEN
+                open_tag := TRUE;
+            ELSIF ch = '}' THEN
+                open_tag := FALSE;
+            END IF;
+            repaired := repaired || ch;
+            i := i + 1;
+        END IF;
+    END LOOP;
+    IF open_tag THEN
+        repaired := repaired || '}';
+    END IF;
+    RETURN repaired;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE;
+
+UPDATE guild_configs SET level_up_message = repair_template(level_up_message)
+    WHERE level_up_message IS DISTINCT FROM repair_template(level_up_message);
+UPDATE guild_configs SET level_up_embed_title = repair_template(level_up_embed_title)
+    WHERE level_up_embed_title IS DISTINCT FROM repair_template(level_up_embed_title);
+UPDATE level_up_messages SET message = repair_template(message)
+    WHERE message IS DISTINCT FROM repair_template(message);
+
+DROP FUNCTION repair_template;
@@ -0,0 +1,16 @@
+-- Add migration script here
+-- Voice sessions on shards with a saved gateway session, since resumed shards don't get told
+-- who is already in voice
+CREATE TABLE voice_sessions (
+    guild INT8 NOT NULL,
+    id INT8 NOT NULL,
+    channel INT8 NOT NULL,
+    afk_channel INT8,
+    bot BOOLEAN NOT NULL,
+    muted BOOLEAN NOT NULL,
+    deafened BOOLEAN NOT NULL,
+    -- milliseconds of activity which had not been paid out yet
+    unpaid INT8 NOT NULL,
+    saved_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (guild, id)
+);
@@ -0,0 +1,85 @@
+use std::{borrow::Cow, collections::HashMap, hint::black_box};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use simpleinterpolation::{Filters, Interpolation};
+
+const TEMPLATE: &str = "GG {user_mention}, you just advanced to level {level}! \
+    You have {xp} xp{?reward_role}, and earned {reward_role}{/reward_role}.";
+
+struct Values {
+    user_id: u64,
+    level: u64,
+    xp: u64,
+    reward_role: Option<u64>,
+}
+
+const VALUES: Values = Values {
+    user_id: 788_222_689_126_776_832,
+    level: 12,
+    xp: 12_345,
+    reward_role: Some(1_041_497_466_735_403_008),
+};
+
+fn lookup(values: &Values, name: &str) -> Option<Cow<'static, str>> {
+    let value = match name {
+        "user_mention" => format!("<@{}>", values.user_id),
+        "level" => values.level.to_string(),
+        "xp" => values.xp.to_string(),
+        "reward_role" => format!("<@&{}>", values.reward_role?),
+        _ => return None,
+    };
+    Some(value.into())
+}
+
+fn render(c: &mut Criterion) {
+    let interpolation = Interpolation::new(TEMPLATE).unwrap();
+    let filters = Filters::new();
+
+    c.bench_function("owned map", |b| {
+        b.iter(|| {
+            let values = black_box(&VALUES);
+            let mut map = HashMap::new();
+            for name in ["user_mention", "level", "xp", "reward_role"] {
+                if let Some(value) = lookup(values, name) {
+                    map.insert(name.to_string(), value.into_owned());
+                }
+            }
+            interpolation.render(&map)
+        });
+    });
+
+    c.bench_function("prebuilt map", |b| {
+        let map: HashMap<String, String> = ["user_mention", "level", "xp", "reward_role"]
+            .into_iter()
+            .filter_map(|name| Some((name.to_string(), lookup(&VALUES, name)?.into_owned())))
+            .collect();
+        b.iter(|| interpolation.render(black_box(&map)));
+    });
+
+    c.bench_function("lookup closure", |b| {
+        let mut output = String::with_capacity(256);
+        b.iter(|| {
+            let values = black_box(&VALUES);
+            output.clear();
+            interpolation
+                .render_to(&mut output, &|name: &str| lookup(values, name), &filters)
+                .unwrap();
+            black_box(&output);
+        });
+    });
+
+    c.bench_function("lookup closure into io", |b| {
+        let mut output = Vec::with_capacity(256);
+        b.iter(|| {
+            let values = black_box(&VALUES);
+            output.clear();
+            interpolation
+                .write_to(&mut output, &|name: &str| lookup(values, name), &filters)
+                .unwrap();
+            black_box(&output);
+        });
+    });
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
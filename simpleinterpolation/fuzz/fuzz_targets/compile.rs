@@ -0,0 +1,19 @@
+#![no_main]
+
+use std::collections::HashMap;
+
+use libfuzzer_sys::fuzz_target;
+use simpleinterpolation::Interpolation;
+
+fuzz_target!(|input: &str| {
+    match Interpolation::new(input) {
+        Ok(interpolation) => {
+            interpolation.render(&HashMap::new());
+            assert_eq!(interpolation.input_value(), input);
+        }
+        Err(error) => {
+            let span = error.span(input);
+            assert!(input.get(span).is_some());
+        }
+    }
+});
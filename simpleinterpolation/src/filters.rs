@@ -0,0 +1,162 @@
+//! Filters change how a variable is displayed, like `{xp:humanize}`.
+//!
+//! No filters are built in to rendering: callers register the ones they want in a [`Filters`],
+//! and pass it to [`Interpolation::render_with_filters`](crate::Interpolation::render_with_filters).
+//! Some common ones are provided in this module.
+use std::{collections::HashMap, fmt::Formatter};
+
+/// A filter which can be applied to a variable's value.
+pub trait Filter: Send + Sync {
+    fn apply(&self, value: &str) -> String;
+}
+
+impl<F> Filter for F
+where
+    F: Fn(&str) -> String + Send + Sync,
+{
+    fn apply(&self, value: &str) -> String {
+        self(value)
+    }
+}
+
+/// A set of named filters to render templates with.
+#[derive(Default)]
+pub struct Filters {
+    filters: HashMap<String, Box<dyn Filter>>,
+}
+
+impl Filters {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a filter, replacing any filter which already had this name.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        filter: impl Filter + 'static,
+    ) -> &mut Self {
+        self.filters.insert(name.into(), Box::new(filter));
+        self
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&dyn Filter> {
+        self.filters.get(name).map(AsRef::as_ref)
+    }
+
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.filters.contains_key(name)
+    }
+
+    /// Apply the filter called `name`, or return `None` if there is no such filter.
+    #[must_use]
+    pub fn apply(&self, name: &str, value: &str) -> Option<String> {
+        self.get(name).map(|filter| filter.apply(value))
+    }
+}
+
+impl std::fmt::Debug for Filters {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.filters.keys()).finish()
+    }
+}
+
+/// Shorten big numbers, like `12300` to `12.3k`.
+#[must_use]
+pub fn humanize_number(num: f64) -> String {
+    let (suffix, scaled) = if (1_000.0..1_000_000.0).contains(&num) {
+        ("k", num / 1_000.0)
+    } else if (1_000_000.0..1_000_000_000.0).contains(&num) {
+        ("m", num / 1_000_000.0)
+    } else if (1_000_000_000.0..1_000_000_000_000.0).contains(&num) {
+        ("b", num / 1_000_000_000.0)
+    } else {
+        ("", num)
+    };
+    let untrimmed = format!("{scaled:.1}");
+    let trimmed = untrimmed.trim_end_matches(".0");
+    format!("{trimmed}{suffix}")
+}
+
+/// [`humanize_number`] as a filter. Values which aren't numbers are left alone.
+#[must_use]
+pub fn humanize(value: &str) -> String {
+    value
+        .parse()
+        .map_or_else(|_| value.to_string(), humanize_number)
+}
+
+/// Turn a whole number into an ordinal, like `1` to `1st`, or `12` to `12th`.
+/// Values which aren't whole numbers are left alone.
+#[must_use]
+pub fn ordinal(value: &str) -> String {
+    let Ok(num) = value.parse::<i128>() else {
+        return value.to_string();
+    };
+    let suffix = match (num.abs() % 10, num.abs() % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{num}{suffix}")
+}
+
+#[must_use]
+pub fn upper(value: &str) -> String {
+    value.to_uppercase()
+}
+
+#[must_use]
+pub fn lower(value: &str) -> String {
+    value.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn humanizes() {
+        assert_eq!(humanize("999"), "999");
+        assert_eq!(humanize("1000"), "1k");
+        assert_eq!(humanize("12345"), "12.3k");
+        assert_eq!(humanize("2500000"), "2.5m");
+        assert_eq!(humanize("not a number"), "not a number");
+    }
+
+    #[test]
+    fn ordinals() {
+        for (num, expected) in [
+            ("0", "0th"),
+            ("1", "1st"),
+            ("2", "2nd"),
+            ("3", "3rd"),
+            ("4", "4th"),
+            ("11", "11th"),
+            ("12", "12th"),
+            ("13", "13th"),
+            ("21", "21st"),
+            ("112", "112th"),
+            ("-1", "-1st"),
+            ("1.5", "1.5"),
+        ] {
+            assert_eq!(ordinal(num), expected);
+        }
+    }
+
+    #[test]
+    fn registry() {
+        let mut filters = Filters::new();
+        filters
+            .register("upper", upper)
+            .register("shout", |value: &str| format!("{value}!"));
+        assert_eq!(filters.apply("upper", "hi").as_deref(), Some("HI"));
+        assert_eq!(filters.apply("shout", "hi").as_deref(), Some("hi!"));
+        assert_eq!(filters.apply("unknown", "hi"), None);
+    }
+}
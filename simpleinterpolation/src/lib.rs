@@ -4,16 +4,186 @@
 //! `this is an {interpolated} string`
 //! Variable names may have `-` `_`, `a-z`, and `A-Z`, any other characters will cause errors.
 //!
-use std::{collections::HashMap, fmt::Formatter};
+//! Variables which are missing or empty can fall back to other variables, or to some text:
+//! `hello {nick|user_name|my friend}`. Every alternative but the last must be a variable name,
+//! and the last one is text if it isn't a valid variable name. Inside fallback text,
+//! `{`, `}`, `|`, `:` and `\` must be escaped with a `\`.
+//!
+//! Sections only render when a variable is present and not empty:
+//! `you hit level {level}{?reward_role}, and earned {reward_role}{/reward_role}!`.
+//! Sections can be nested, but must be closed in the opposite order they were opened in.
+//!
+//! Variables can be passed through [`filters`] before they are displayed, like `{xp:humanize}`
+//! or `{nick|user_name:upper}`. Filters are applied in order, after any fallbacks.
+//!
+//! With the `serde` feature, an [`Interpolation`] (de)serializes as its template string,
+//! which is checked while deserializing.
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::{Formatter, Write},
+    io,
+    ops::Range,
+};
+
+pub mod filters;
+pub mod lookup;
+#[cfg(feature = "serde")]
+mod serde_impl;
+
+pub use filters::{Filter, Filters};
+pub use lookup::Lookup;
 
 #[derive(Clone, Debug, Hash, Eq, PartialEq)]
 pub struct Interpolation {
-    // The first value is the raw value that will be appended
-    // to the final string. The second value will go AFTER this string,
-    // but it is dynamic
-    parts: Vec<(String, String)>,
-    // The value which is placed after the otherwise rendered interpolation
-    end: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+enum Segment {
+    /// Text which is copied into the output as-is
+    Text(String),
+    /// A `{variable}`
+    Variable(Substitution),
+    /// A `{?variable}...{/variable}` section
+    Conditional { name: String, body: Vec<Segment> },
+}
+
+/// A single `{variable}`, along with whatever it falls back to.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+struct Substitution {
+    name: String,
+    // Variables tried in order when the ones before them are missing or empty
+    fallbacks: Vec<String>,
+    // Text used when every variable is missing or empty
+    default: Option<String>,
+    // Names of filters to apply to the value, in order
+    filters: Vec<String>,
+}
+
+impl Substitution {
+    fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.name.as_str()).chain(self.fallbacks.iter().map(String::as_str))
+    }
+
+    fn render<'a, W: Write + ?Sized, L: Lookup<'a> + ?Sized>(
+        &self,
+        output: &mut W,
+        lookup: &L,
+        filters: &Filters,
+    ) -> std::fmt::Result {
+        let found = self
+            .names()
+            .filter_map(|name| lookup.lookup(name))
+            .find(|value| !value.is_empty());
+        let mut value = found.unwrap_or(Cow::Borrowed(self.default.as_deref().unwrap_or("")));
+        for filter in &self.filters {
+            if let Some(filtered) = filters.apply(filter, &value) {
+                value = filtered.into();
+            }
+        }
+        output.write_str(&value)
+    }
+}
+
+impl Segment {
+    fn render<'a, W: Write + ?Sized, L: Lookup<'a> + ?Sized>(
+        &self,
+        output: &mut W,
+        lookup: &L,
+        filters: &Filters,
+    ) -> std::fmt::Result {
+        match self {
+            Self::Text(text) => output.write_str(text),
+            Self::Variable(substitution) => substitution.render(output, lookup, filters),
+            Self::Conditional { name, body } => {
+                if lookup.lookup(name).is_some_and(|value| !value.is_empty()) {
+                    for segment in body {
+                        segment.render(output, lookup, filters)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn variables_used<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Self::Text(_) => {}
+            Self::Variable(substitution) => names.extend(substitution.names()),
+            Self::Conditional { name, body } => {
+                names.push(name);
+                for segment in body {
+                    segment.variables_used(names);
+                }
+            }
+        }
+    }
+
+    fn filters_used<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Self::Text(_) => {}
+            Self::Variable(substitution) => {
+                names.extend(substitution.filters.iter().map(String::as_str));
+            }
+            Self::Conditional { body, .. } => {
+                for segment in body {
+                    segment.filters_used(names);
+                }
+            }
+        }
+    }
+
+    fn input_value(&self, output: &mut String) {
+        fn push_escape(s: &mut String, txt: &str, special: &[char]) {
+            for next in txt.chars() {
+                if special.contains(&next) {
+                    s.push('\\');
+                }
+                s.push(next);
+            }
+        }
+
+        match self {
+            Self::Text(text) => push_escape(output, text, InterpolationCompiler::TEXT_ESCAPES),
+            Self::Variable(substitution) => {
+                output.push('{');
+                output.push_str(&substitution.name);
+                for fallback in &substitution.fallbacks {
+                    output.push('|');
+                    output.push_str(fallback);
+                }
+                if let Some(default) = &substitution.default {
+                    output.push('|');
+                    push_escape(output, default, InterpolationCompiler::DEFAULT_ESCAPES);
+                }
+                for filter in &substitution.filters {
+                    output.push(':');
+                    output.push_str(filter);
+                }
+                output.push('}');
+            }
+            Self::Conditional { name, body } => {
+                output.push_str("{?");
+                output.push_str(name);
+                output.push('}');
+                for segment in body {
+                    segment.input_value(output);
+                }
+                output.push_str("{/");
+                output.push_str(name);
+                output.push('}');
+            }
+        }
+    }
+
+    fn size_hint(&self) -> usize {
+        match self {
+            Self::Text(text) => text.len(),
+            Self::Variable(_) => Interpolation::REASONABLE_INTERPOLATION_PREALLOC_BYTES,
+            Self::Conditional { body, .. } => body.iter().map(Self::size_hint).sum(),
+        }
+    }
 }
 
 impl Interpolation {
@@ -24,144 +194,319 @@ impl Interpolation {
     }
 
     fn output_string(&self) -> String {
-        String::with_capacity(
-            self.parts
-                .iter()
-                .map(|v| v.0.len() + Self::REASONABLE_INTERPOLATION_PREALLOC_BYTES)
-                .sum(),
-        )
+        String::with_capacity(self.segments.iter().map(Segment::size_hint).sum())
     }
 
+    /// Render without any filters. Filtered variables are displayed as they are, and so are
+    /// variables which use filters `filters` doesn't have in [`Self::render_with_filters`].
     pub fn render(&self, args: &HashMap<String, String>) -> String {
+        self.render_with_filters(args, &Filters::new())
+    }
+
+    pub fn render_with_filters(&self, args: &HashMap<String, String>, filters: &Filters) -> String {
         let mut output = self.output_string();
-        for (raw, interpolation_key) in &self.parts {
-            output.push_str(raw);
-            let interpolation_value = args.get(interpolation_key);
-            output.push_str(interpolation_value.unwrap_or(&String::new()));
-        }
-        output.push_str(&self.end);
+        // writing to a String never fails
+        let _ = self.render_to(&mut output, &args, filters);
         output
     }
 
+    /// Render into any [`std::fmt::Write`], looking variables up with `lookup`.
+    /// This doesn't allocate unless `lookup` or a filter does.
+    ///
+    /// # Errors
+    /// This only errors if `output` does.
+    pub fn render_to<'a, W: Write + ?Sized, L: Lookup<'a> + ?Sized>(
+        &self,
+        output: &mut W,
+        lookup: &L,
+        filters: &Filters,
+    ) -> std::fmt::Result {
+        for segment in &self.segments {
+            segment.render(output, lookup, filters)?;
+        }
+        Ok(())
+    }
+
+    /// Like [`Self::render_to`], but for any [`std::io::Write`].
+    ///
+    /// # Errors
+    /// This only errors if `output` does.
+    pub fn write_to<'a, W: io::Write + ?Sized, L: Lookup<'a> + ?Sized>(
+        &self,
+        output: &mut W,
+        lookup: &L,
+        filters: &Filters,
+    ) -> io::Result<()> {
+        let mut adapter = IoAdapter {
+            inner: output,
+            error: None,
+        };
+        self.render_to(&mut adapter, lookup, filters).map_err(|_| {
+            adapter
+                .error
+                .unwrap_or_else(|| io::Error::other("formatter error"))
+        })
+    }
+
+    /// Every variable this template may look up, including fallbacks and sections.
     pub fn variables_used(&self) -> impl Iterator<Item = &str> {
-        UsedVariablesIterator {
-            inner: self.parts.as_slice(),
-            current: 0,
+        let mut names = Vec::new();
+        for segment in &self.segments {
+            segment.variables_used(&mut names);
         }
+        names.into_iter()
     }
 
-    pub fn input_value(&self) -> String {
-        fn push_escape(s: &mut String, txt: &str) {
-            for next in txt.chars() {
-                if next == '{' || next == '\\' {
-                    s.push('\\');
-                }
-                s.push(next);
-            }
+    /// Every filter this template uses.
+    pub fn filters_used(&self) -> impl Iterator<Item = &str> {
+        let mut names = Vec::new();
+        for segment in &self.segments {
+            segment.filters_used(&mut names);
         }
+        names.into_iter()
+    }
 
+    pub fn input_value(&self) -> String {
         let mut output = self.output_string();
-        for (text, key) in &self.parts {
-            push_escape(&mut output, text);
-            output.push('{');
-            output.push_str(key);
-            output.push('}');
+        for segment in &self.segments {
+            segment.input_value(&mut output);
         }
-        push_escape(&mut output, &self.end);
         output
     }
 }
 
-struct UsedVariablesIterator<'a> {
-    inner: &'a [(String, String)],
-    current: usize,
+/// Lets [`Interpolation::render_to`] write into an [`io::Write`], keeping hold of the real error.
+struct IoAdapter<'w, W: ?Sized> {
+    inner: &'w mut W,
+    error: Option<io::Error>,
 }
 
-impl<'a> Iterator for UsedVariablesIterator<'a> {
-    type Item = &'a str;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let next = self.inner.get(self.current)?.1.as_str();
-        self.current += 1;
-        Some(next)
+impl<W: io::Write + ?Sized> Write for IoAdapter<'_, W> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|source| {
+            self.error = Some(source);
+            std::fmt::Error
+        })
     }
 }
 
+/// A section which has been opened, but not closed yet.
+struct OpenSection {
+    name: String,
+    // Index of the `{` which opened this section
+    start: usize,
+    body: Vec<Segment>,
+}
+
 struct InterpolationCompiler {
     chars: Vec<char>,
-    parts: Vec<(String, String)>,
+    segments: Vec<Segment>,
+    sections: Vec<OpenSection>,
     index: usize,
     next: String,
-    escaped: bool,
 }
 
 impl InterpolationCompiler {
+    const DEFAULT_ESCAPES: &'static [char] = &['{', '}', '|', ':', '\\'];
+    const TEXT_ESCAPES: &'static [char] = &['{', '\\'];
+
     fn compile(input: &str) -> Result<Interpolation, Error> {
         let mut compiler = Self {
             chars: input.chars().collect(),
-            parts: Vec::new(),
+            segments: Vec::new(),
+            sections: Vec::new(),
             index: 0,
             next: String::new(),
-            escaped: false,
         };
 
-        // for each character, check if the character is a
-        while let Some(character) = compiler.chars.get(compiler.index).copied() {
-            compiler.handle_char(character)?;
+        while let Some(ch) = compiler.bump() {
+            match ch {
+                '\\' => {
+                    let escaped = compiler.escape(Self::TEXT_ESCAPES)?;
+                    compiler.next.push(escaped);
+                }
+                '{' => compiler.make_tag()?,
+                _ => compiler.next.push(ch),
+            }
         }
 
-        compiler.parts.shrink_to_fit();
+        if let Some(section) = compiler.sections.last() {
+            return Err(Error::UnclosedSection(section.start));
+        }
+        compiler.flush_text();
+        compiler.segments.shrink_to_fit();
 
         Ok(Interpolation {
-            parts: compiler.parts,
-            end: compiler.next,
+            segments: compiler.segments,
         })
     }
 
-    fn handle_char(&mut self, ch: char) -> Result<(), Error> {
-        if self.escaped && ch != '{' && ch != '\\' {
-            return Err(Error::InvalidEscape(ch, self.index));
-        } else if self.escaped {
-            self.next.push(ch);
-            self.escaped = false;
-        } else if ch == '\\' {
-            self.escaped = true;
-        } else if ch == '{' {
-            self.index += 1;
-            let mut ident = self.make_identifier()?;
-            let mut to_push = std::mem::take(&mut self.next);
-            ident.shrink_to_fit();
-            to_push.shrink_to_fit();
-            self.parts.push((to_push, ident));
-        } else {
-            self.next.push(ch);
-        };
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    /// Move past the next character, returning it
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.peek()?;
         self.index += 1;
+        Some(ch)
+    }
+
+    /// Read the character after a `\`, which has already been consumed
+    fn escape(&mut self, allowed: &'static [char]) -> Result<char, Error> {
+        match self.bump() {
+            Some(ch) if allowed.contains(&ch) => Ok(ch),
+            Some(ch) => Err(Error::InvalidEscape(ch, self.index - 1, allowed)),
+            None => Err(Error::UnfinishedEscape(self.index - 1)),
+        }
+    }
+
+    /// Consume the `}` ending the tag opened at `start`
+    fn close_tag(&mut self, start: usize) -> Result<(), Error> {
+        match self.bump() {
+            Some('}') => Ok(()),
+            Some(ch) => Err(Error::InvalidCharInIdentifier(ch, self.index - 1)),
+            None => Err(Error::UnclosedIdentifier(start)),
+        }
+    }
+
+    /// Compile whatever follows a `{`, which has already been consumed
+    fn make_tag(&mut self) -> Result<(), Error> {
+        let start = self.index - 1;
+        self.flush_text();
+        match self.peek() {
+            Some('?') => {
+                self.index += 1;
+                let name = self.make_identifier();
+                self.close_tag(start)?;
+                self.sections.push(OpenSection {
+                    name,
+                    start,
+                    body: Vec::new(),
+                });
+            }
+            Some('/') => {
+                self.index += 1;
+                let name = self.make_identifier();
+                self.close_tag(start)?;
+                let section = self
+                    .sections
+                    .pop_if(|section| section.name == name)
+                    .ok_or(Error::MismatchedSectionEnd(start))?;
+                let mut body = section.body;
+                body.shrink_to_fit();
+                self.push_segment(Segment::Conditional {
+                    name: section.name,
+                    body,
+                });
+            }
+            _ => {
+                let substitution = self.make_substitution(start)?;
+                self.push_segment(Segment::Variable(substitution));
+            }
+        }
         Ok(())
     }
 
+    /// The segment list that new segments should be added to
+    fn push_segment(&mut self, segment: Segment) {
+        match self.sections.last_mut() {
+            Some(section) => section.body.push(segment),
+            None => self.segments.push(segment),
+        }
+    }
+
+    fn flush_text(&mut self) {
+        if !self.next.is_empty() {
+            let mut text = std::mem::take(&mut self.next);
+            text.shrink_to_fit();
+            self.push_segment(Segment::Text(text));
+        }
+    }
+
     #[inline]
     fn valid_ident_char(ch: char) -> bool {
         matches!(ch, 'A'..='Z' | 'a'..='z' | '_' | '-')
     }
 
-    fn make_identifier(&mut self) -> Result<String, Error> {
+    /// Read identifier characters, stopping before the first character which isn't one.
+    /// Whoever calls this decides whether that character is allowed there.
+    fn make_identifier(&mut self) -> String {
         let mut identifier = String::new();
-        let start = self.index;
-        while let Some(identifier_part) = self.chars.get(self.index).copied() {
-            if identifier_part == '}' {
-                break;
+        while let Some(ch) = self.peek().filter(|ch| Self::valid_ident_char(*ch)) {
+            identifier.push(ch);
+            self.index += 1;
+        }
+        identifier.shrink_to_fit();
+        identifier
+    }
+
+    fn make_substitution(&mut self, start: usize) -> Result<Substitution, Error> {
+        let mut substitution = Substitution {
+            name: self.make_identifier(),
+            fallbacks: Vec::new(),
+            default: None,
+            filters: Vec::new(),
+        };
+        while self.peek() == Some('|') {
+            self.index += 1;
+            let (alternative, invalid) = self.make_alternative(start)?;
+            let last = matches!(self.peek(), Some('}' | ':'));
+            match invalid {
+                None if !alternative.is_empty() => substitution.fallbacks.push(alternative),
+                _ if last => substitution.default = Some(alternative),
+                // only the last alternative can be text
+                Some((ch, at)) => return Err(Error::InvalidCharInIdentifier(ch, at)),
+                None => return Err(Error::InvalidCharInIdentifier('|', self.index)),
             }
-            if self.index >= self.chars.len() {
-                return Err(Error::UnclosedIdentifier(start));
+        }
+        while self.peek() == Some(':') {
+            self.index += 1;
+            let filter = self.make_identifier();
+            if filter.is_empty() {
+                return match self.peek() {
+                    Some(ch) => Err(Error::InvalidCharInIdentifier(ch, self.index)),
+                    None => Err(Error::UnclosedIdentifier(start)),
+                };
             }
-            if !Self::valid_ident_char(identifier_part) {
-                return Err(Error::InvalidCharInIdentifier(identifier_part, self.index));
+            substitution.filters.push(filter);
+        }
+        // filters come after every fallback, so a `|` here is also wrong
+        self.close_tag(start)?;
+        substitution.fallbacks.shrink_to_fit();
+        substitution.filters.shrink_to_fit();
+        Ok(substitution)
+    }
+
+    /// Read one `|` separated alternative, stopping on the `|`, `:` or `}` after it.
+    /// Also returns the first thing that stops it from being a variable name, if any.
+    fn make_alternative(&mut self, start: usize) -> Result<(String, Option<(char, usize)>), Error> {
+        let mut alternative = String::new();
+        let mut invalid = None;
+        loop {
+            let at = self.index;
+            match self.bump() {
+                None => return Err(Error::UnclosedIdentifier(start)),
+                Some('}' | '|' | ':') => {
+                    // leave it for the caller
+                    self.index -= 1;
+                    break;
+                }
+                Some('{') => return Err(Error::InvalidCharInIdentifier('{', at)),
+                Some('\\') => {
+                    invalid = invalid.or(Some(('\\', at)));
+                    alternative.push(self.escape(Self::DEFAULT_ESCAPES)?);
+                }
+                Some(ch) => {
+                    if !Self::valid_ident_char(ch) {
+                        invalid = invalid.or(Some((ch, at)));
+                    }
+                    alternative.push(ch);
+                }
             }
-            identifier.push(identifier_part);
-            self.index += 1;
         }
-        Ok(identifier)
+        alternative.shrink_to_fit();
+        Ok((alternative, invalid))
     }
 }
 
@@ -169,7 +514,10 @@ impl InterpolationCompiler {
 pub enum Error {
     UnclosedIdentifier(usize),
     InvalidCharInIdentifier(char, usize),
-    InvalidEscape(char, usize),
+    InvalidEscape(char, usize, &'static [char]),
+    UnclosedSection(usize),
+    MismatchedSectionEnd(usize),
+    UnfinishedEscape(usize),
 }
 
 impl std::fmt::Display for Error {
@@ -181,19 +529,85 @@ impl std::fmt::Display for Error {
             Self::InvalidCharInIdentifier(c, at) => {
                 write!(f, "Invalid character `{c:?}` in identifier at {}", at + 1)
             }
-            Self::InvalidEscape(c, at) => {
+            Self::InvalidEscape(c, at, allowed) => {
+                write!(f, "`{c:?}` at position {} cannot be escaped, only ", at + 1)?;
+                for (i, ch) in allowed.iter().enumerate() {
+                    match i {
+                        0 => {}
+                        _ if i + 1 == allowed.len() => f.write_str(" and ")?,
+                        _ => f.write_str(", ")?,
+                    }
+                    write!(f, "`{ch}`")?;
+                }
+                f.write_str(" can be here")
+            }
+            Self::UnclosedSection(at) => {
+                write!(f, "Section opened at {} is never closed", at + 1)
+            }
+            Self::MismatchedSectionEnd(at) => {
                 write!(
                     f,
-                    "`{c:?}` at position {} cannot be escaped, only `{{` and `\\` can",
+                    "Section end at {} does not match the most recently opened section",
                     at + 1
                 )
             }
+            Self::UnfinishedEscape(at) => {
+                write!(f, "Nothing was escaped by the `\\` at {}", at + 1)
+            }
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+impl Error {
+    /// Index of the character which caused this error, counted in `char`s.
+    #[must_use]
+    pub const fn position(&self) -> usize {
+        match self {
+            Self::UnclosedIdentifier(at)
+            | Self::InvalidCharInIdentifier(_, at)
+            | Self::InvalidEscape(_, at, _)
+            | Self::UnclosedSection(at)
+            | Self::MismatchedSectionEnd(at)
+            | Self::UnfinishedEscape(at) => *at,
+        }
+    }
+
+    /// The bytes of `template` which caused this error, for pointing them out to whoever wrote it.
+    /// `template` must be the string this error came from.
+    #[must_use]
+    pub fn span(&self, template: &str) -> Range<usize> {
+        let byte_at = |char_index: usize| {
+            template
+                .char_indices()
+                .nth(char_index)
+                .map_or(template.len(), |(byte, _)| byte)
+        };
+        // the whole tag, from its `{` to its `}`
+        let tag = |at: usize| {
+            let start = byte_at(at);
+            let end = template[start..]
+                .find('}')
+                .map_or(template.len(), |close| start + close + 1);
+            start..end
+        };
+        match *self {
+            Self::UnclosedIdentifier(at) => byte_at(at)..template.len(),
+            Self::InvalidCharInIdentifier(ch, at) => {
+                let start = byte_at(at);
+                start..start + ch.len_utf8()
+            }
+            // include the backslash
+            Self::InvalidEscape(ch, at, _) => {
+                byte_at(at.saturating_sub(1))..byte_at(at) + ch.len_utf8()
+            }
+            Self::UnclosedSection(at) | Self::MismatchedSectionEnd(at) => tag(at),
+            Self::UnfinishedEscape(at) => byte_at(at)..template.len(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -263,10 +677,339 @@ mod tests {
         assert_eq!(roundtrip, interpolation.input_value());
     }
     #[test]
+    fn fallback_variable() {
+        let interpolation = Interpolation::new("Hi {nick|user_name}!").unwrap();
+        let mut args = HashMap::from([("user_name".to_string(), "valk".to_string())]);
+        assert_eq!("Hi valk!", interpolation.render(&args));
+        args.insert("nick".to_string(), String::new());
+        assert_eq!("Hi valk!", interpolation.render(&args));
+        args.insert("nick".to_string(), "Valkyrie".to_string());
+        assert_eq!("Hi Valkyrie!", interpolation.render(&args));
+        assert_eq!(
+            interpolation.variables_used().collect::<Vec<&str>>(),
+            vec!["nick", "user_name"]
+        );
+    }
+    #[test]
+    fn fallback_text() {
+        let interpolation = Interpolation::new("Hi {nick|user_name|my friend}!").unwrap();
+        assert_eq!("Hi my friend!", interpolation.render(&HashMap::new()));
+        assert_eq!(
+            interpolation.variables_used().collect::<Vec<&str>>(),
+            vec!["nick", "user_name"]
+        );
+    }
+    #[test]
+    fn fallback_text_escapes() {
+        let interpolation = Interpolation::new("{nick|a \\{b\\} \\| c\\\\}").unwrap();
+        assert_eq!("a {b} | c\\", interpolation.render(&HashMap::new()));
+    }
+    #[test]
+    fn fallback_roundtrip() {
+        for roundtrip in [
+            "Hi {nick|user_name}!",
+            "Hi {nick|user_name|my friend}!",
+            "Hi {nick|}!",
+            "{nick|a \\{b\\} \\| c\\\\}",
+        ] {
+            let interpolation = Interpolation::new(roundtrip).unwrap();
+            println!("{interpolation:?}");
+            assert_eq!(roundtrip, interpolation.input_value());
+        }
+    }
+    #[test]
+    fn fallback_errors() {
+        assert!(matches!(
+            Interpolation::new("{nick|some text|user_name}"),
+            Err(Error::InvalidCharInIdentifier(' ', 10))
+        ));
+        assert!(matches!(
+            Interpolation::new("{nick|oops"),
+            Err(Error::UnclosedIdentifier(0))
+        ));
+        assert!(matches!(
+            Interpolation::new("{nick|\\n}"),
+            Err(Error::InvalidEscape('n', 7, _))
+        ));
+        assert_eq!(
+            Interpolation::new("{nick|\\n}").unwrap_err().to_string(),
+            "`'n'` at position 8 cannot be escaped, only `{`, `}`, `|`, `:` and `\\` can be here"
+        );
+        assert_eq!(
+            Interpolation::new("a \\n").unwrap_err().to_string(),
+            "`'n'` at position 4 cannot be escaped, only `{` and `\\` can be here"
+        );
+    }
+    #[test]
+    fn conditional() {
+        let interpolation = Interpolation::new(
+            "GG {user_mention}, you hit level {level}{?reward_role}, and earned {reward_role}{/reward_role}!",
+        )
+        .unwrap();
+        let mut args = HashMap::from([
+            ("user_mention".to_string(), "@valk".to_string()),
+            ("level".to_string(), "5".to_string()),
+        ]);
+        assert_eq!("GG @valk, you hit level 5!", interpolation.render(&args));
+        args.insert("reward_role".to_string(), String::new());
+        assert_eq!("GG @valk, you hit level 5!", interpolation.render(&args));
+        args.insert("reward_role".to_string(), "@Regular".to_string());
+        assert_eq!(
+            "GG @valk, you hit level 5, and earned @Regular!",
+            interpolation.render(&args)
+        );
+        assert_eq!(
+            interpolation.variables_used().collect::<Vec<&str>>(),
+            vec!["user_mention", "level", "reward_role", "reward_role"]
+        );
+    }
+    #[test]
+    fn nested_conditional() {
+        let interpolation = Interpolation::new("{?a}a{?b}b{/b}{/a}").unwrap();
+        let args = HashMap::from([("b".to_string(), "yes".to_string())]);
+        assert_eq!("", interpolation.render(&args));
+        let args = HashMap::from([
+            ("a".to_string(), "yes".to_string()),
+            ("b".to_string(), "yes".to_string()),
+        ]);
+        assert_eq!("ab", interpolation.render(&args));
+    }
+    #[test]
+    fn conditional_roundtrip() {
+        for roundtrip in [
+            "GG {user_mention}{?reward_role}, and earned {reward_role|nothing}{/reward_role}!",
+            "{?a}\\{?b}{?b}b{/b}{/a}",
+            "{?a}{/a}",
+        ] {
+            let interpolation = Interpolation::new(roundtrip).unwrap();
+            println!("{interpolation:?}");
+            assert_eq!(roundtrip, interpolation.input_value());
+        }
+    }
+    #[test]
+    fn conditional_errors() {
+        assert!(matches!(
+            Interpolation::new("hi {?a}there"),
+            Err(Error::UnclosedSection(3))
+        ));
+        assert!(matches!(
+            Interpolation::new("{?a}{?b}{/a}{/b}"),
+            Err(Error::MismatchedSectionEnd(8))
+        ));
+        assert!(matches!(
+            Interpolation::new("hi{/a}"),
+            Err(Error::MismatchedSectionEnd(2))
+        ));
+        assert!(matches!(
+            Interpolation::new("{?a|b}{/a}"),
+            Err(Error::InvalidCharInIdentifier('|', 3))
+        ));
+        assert!(matches!(
+            Interpolation::new("{?a"),
+            Err(Error::UnclosedIdentifier(0))
+        ));
+    }
+    #[test]
+    fn filters() {
+        let mut filters = Filters::new();
+        filters
+            .register("upper", filters::upper)
+            .register("humanize", filters::humanize);
+        let interpolation =
+            Interpolation::new("{nick|user_name:upper} has {xp:humanize} xp{unknown:nope}")
+                .unwrap();
+        let args = HashMap::from([
+            ("user_name".to_string(), "valk".to_string()),
+            ("xp".to_string(), "12345".to_string()),
+            ("unknown".to_string(), "!".to_string()),
+        ]);
+        assert_eq!(
+            "VALK has 12.3k xp!",
+            interpolation.render_with_filters(&args, &filters)
+        );
+        assert_eq!("valk has 12345 xp!", interpolation.render(&args));
+        assert_eq!(
+            interpolation.filters_used().collect::<Vec<&str>>(),
+            vec!["upper", "humanize", "nope"]
+        );
+    }
+    #[test]
+    fn chained_filters() {
+        let mut filters = Filters::new();
+        filters
+            .register("upper", filters::upper)
+            .register("ordinal", filters::ordinal);
+        let interpolation = Interpolation::new("{level:ordinal:upper}").unwrap();
+        let args = HashMap::from([("level".to_string(), "2".to_string())]);
+        assert_eq!("2ND", interpolation.render_with_filters(&args, &filters));
+    }
+    #[test]
+    fn filter_roundtrip() {
+        for roundtrip in [
+            "{xp:humanize}",
+            "{level:ordinal:upper}",
+            "{nick|user_name|some\\: text:upper}",
+        ] {
+            let interpolation = Interpolation::new(roundtrip).unwrap();
+            println!("{interpolation:?}");
+            assert_eq!(roundtrip, interpolation.input_value());
+        }
+    }
+    #[test]
+    fn filter_errors() {
+        assert!(matches!(
+            Interpolation::new("{xp:humanize|level}"),
+            Err(Error::InvalidCharInIdentifier('|', 12))
+        ));
+        assert!(matches!(
+            Interpolation::new("{xp:}"),
+            Err(Error::InvalidCharInIdentifier('}', 4))
+        ));
+        assert!(matches!(
+            Interpolation::new("{xp:hu manize}"),
+            Err(Error::InvalidCharInIdentifier(' ', 6))
+        ));
+    }
+    #[test]
+    fn lookup_closure() {
+        let interpolation =
+            Interpolation::new("{name} is level {level}{?missing}!{/missing}").unwrap();
+        let level = 5;
+        let lookup = |name: &str| match name {
+            "name" => Some(Cow::Borrowed("valk")),
+            "level" => Some(Cow::Owned(level.to_string())),
+            _ => None,
+        };
+        let mut output = String::new();
+        interpolation
+            .render_to(&mut output, &lookup, &Filters::new())
+            .unwrap();
+        assert_eq!("valk is level 5", output);
+    }
+    #[test]
+    fn lookup_trait_object() {
+        let interpolation = Interpolation::new("{interpolation} {name}. ").unwrap();
+        let args = get_example_args();
+        let map = &args;
+        let closure = |name: &str| Some(Cow::Borrowed(if name == "name" { "valk" } else { "?" }));
+        let lookups: [&dyn Lookup; 2] = [&map, &closure];
+        let mut output = String::new();
+        for lookup in lookups {
+            interpolation
+                .render_to(&mut output, lookup, &Filters::new())
+                .unwrap();
+        }
+        assert_eq!("Interpolation . ? valk. ", output);
+    }
+    #[test]
+    fn write_to_io() {
+        let interpolation =
+            Interpolation::new("This is an example string for {interpolation}!").unwrap();
+        let mut output = Vec::new();
+        interpolation
+            .write_to(&mut output, &&get_example_args(), &Filters::new())
+            .unwrap();
+        assert_eq!(
+            "This is an example string for Interpolation!".as_bytes(),
+            output
+        );
+    }
+    #[test]
+    fn error_spans() {
+        let cases = [
+            ("{nick|oops", "{nick|oops"),
+            ("héllo {wör ld}", "ö"),
+            ("héllo {wor ld}", " "),
+            ("é\\n", "\\n"),
+            ("{nick|\\né}", "\\n"),
+            ("ü {?a}there", "{?a}"),
+            ("{?a}{?b}{/a}{/b}", "{/a}"),
+        ];
+        for (template, offending) in cases {
+            let error = Interpolation::new(template).unwrap_err();
+            assert_eq!(&template[error.span(template)], offending, "{error:?}");
+        }
+    }
+    #[test]
+    fn unclosed_identifiers() {
+        for (template, at) in [
+            ("hello {world", 6),
+            ("hello {", 6),
+            ("{xp:humanize", 0),
+            ("{xp:", 0),
+            ("{?a", 0),
+            ("a{/a", 1),
+        ] {
+            assert!(
+                matches!(Interpolation::new(template), Err(Error::UnclosedIdentifier(pos)) if pos == at),
+                "{template}"
+            );
+        }
+    }
+    #[test]
+    fn trailing_escape() {
+        assert!(matches!(
+            Interpolation::new("hello \\"),
+            Err(Error::UnfinishedEscape(6))
+        ));
+        assert!(matches!(
+            Interpolation::new("{a|b\\"),
+            Err(Error::UnfinishedEscape(4))
+        ));
+    }
+    #[test]
     fn no_interpolation() {
         let unchanged = "This is an example string for a lack of interpolation!";
         let interpolation = Interpolation::new(unchanged).unwrap();
         println!("{interpolation:?}");
         assert_eq!(unchanged, interpolation.render(&HashMap::new()));
     }
+
+    mod properties {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        /// Mostly characters which mean something to the compiler, so that valid templates
+        /// of every shape come up, along with some multibyte ones.
+        fn template() -> impl Strategy<Value = String> {
+            proptest::collection::vec(
+                prop_oneof![
+                    3 => proptest::sample::select(vec!['{', '}', '?', '/', '|', ':', '\\']),
+                    3 => proptest::sample::select(vec!['a', 'b', '_', '-']),
+                    1 => proptest::sample::select(vec![' ', 'é', '🦀', '\n']),
+                ],
+                0..32,
+            )
+            .prop_map(|chars| chars.into_iter().collect())
+        }
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(4096))]
+
+            #[test]
+            fn valid_templates_roundtrip(input in template()) {
+                if let Ok(interpolation) = Interpolation::new(&input) {
+                    prop_assert_eq!(interpolation.input_value(), input);
+                }
+            }
+
+            #[test]
+            fn error_spans_are_in_bounds(input in template()) {
+                if let Err(error) = Interpolation::new(&input) {
+                    let span = error.span(&input);
+                    prop_assert!(span.start <= span.end && span.end <= input.len());
+                    prop_assert!(input.get(span).is_some());
+                }
+            }
+
+            #[test]
+            fn arbitrary_strings_never_panic(input in any::<String>()) {
+                if let Ok(interpolation) = Interpolation::new(&input) {
+                    interpolation.render(&HashMap::new());
+                    prop_assert_eq!(interpolation.input_value(), input);
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,35 @@
+//! Where templates get the values of their variables from.
+use std::{
+    borrow::{Borrow, Cow},
+    collections::HashMap,
+    hash::{BuildHasher, Hash},
+};
+
+/// Something which can find the value of a variable for a template.
+///
+/// This is implemented for maps of strings, and for closures which take a variable name,
+/// like `|name: &str| -> Option<Cow<str>>`. That way, values can be borrowed from wherever they
+/// already live, or only built when a template actually uses them.
+pub trait Lookup<'a> {
+    fn lookup(&self, name: &str) -> Option<Cow<'a, str>>;
+}
+
+impl<'a, F> Lookup<'a> for F
+where
+    F: Fn(&str) -> Option<Cow<'a, str>>,
+{
+    fn lookup(&self, name: &str) -> Option<Cow<'a, str>> {
+        self(name)
+    }
+}
+
+impl<'a, K, V, S> Lookup<'a> for &'a HashMap<K, V, S>
+where
+    K: Borrow<str> + Hash + Eq,
+    V: AsRef<str>,
+    S: BuildHasher,
+{
+    fn lookup(&self, name: &str) -> Option<Cow<'a, str>> {
+        self.get(name).map(|value| Cow::Borrowed(value.as_ref()))
+    }
+}
@@ -0,0 +1,52 @@
+use std::fmt::Formatter;
+
+use serde::{de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Interpolation;
+
+impl Serialize for Interpolation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.input_value())
+    }
+}
+
+struct InterpolationVisitor;
+
+impl Visitor<'_> for InterpolationVisitor {
+    type Value = Interpolation;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str("a simpleinterpolation template string")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Interpolation::new(v).map_err(E::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Interpolation {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(InterpolationVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let template = "GG {user_mention}{?reward_role}, and earned {reward_role}{/reward_role}!";
+        let interpolation = Interpolation::new(template).unwrap();
+        let json = serde_json::to_string(&interpolation).unwrap();
+        assert_eq!(json, serde_json::to_string(template).unwrap());
+        let back: Interpolation = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, interpolation);
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        let error = serde_json::from_str::<Interpolation>(r#""{?a}never closed""#).unwrap_err();
+        assert!(error.to_string().contains("never closed"), "{error}");
+    }
+}
@@ -3,16 +3,21 @@
 use std::{
     borrow::Cow,
     fmt::{Display, Formatter},
+    future::Future,
+    num::NonZeroU64,
     str::FromStr,
 };
 
+use mee6::{Curve, Exponential, Linear, Mee6, Table, Wall};
 use simpleinterpolation::Interpolation;
+use tracing::warn;
 use twilight_gateway::EventTypeFlags;
+use twilight_http::error::ErrorType;
 use twilight_model::{
     gateway::Intents,
     guild::Member,
     id::{
-        marker::{ChannelMarker, RoleMarker, UserMarker},
+        marker::{ChannelMarker, GuildMarker, RoleMarker, UserMarker},
         Id,
     },
     user::User,
@@ -162,10 +167,60 @@ pub fn db_to_id<T>(db: i64) -> Id<T> {
     Id::new(db.reinterpret_bits())
 }
 
-pub const TEMPLATE_VARIABLES: [&str; 2] = ["user_mention", "level"];
+/// Whether a request failed because Discord couldn't find what it was asked about,
+/// such as a member who has since left the guild.
+#[must_use]
+pub const fn is_not_found(source: &twilight_http::Error) -> bool {
+    matches!(source.kind(), ErrorType::Response { status, .. } if status.get() == 404)
+}
+
+/// Every variable level-up messages may use. Templates using anything else are rejected when saved.
+pub const TEMPLATE_VARIABLES: [&str; 12] = [
+    "user_mention",
+    "user_name",
+    "display_name",
+    "level",
+    "old_level",
+    "xp",
+    "next_level_xp",
+    "rank",
+    "guild_name",
+    "channel_mention",
+    "reward_role",
+    "prestige",
+];
+/// Every filter level-up messages may use, like `{xp:humanize}`.
+pub const TEMPLATE_FILTERS: [&str; 4] = ["humanize", "ordinal", "upper", "lower"];
 pub const DEFAULT_MAX_XP_PER_MESSAGE: i16 = 25;
 pub const DEFAULT_MIN_XP_PER_MESSAGE: i16 = 15;
 pub const DEFAULT_MESSAGE_COOLDOWN: i16 = 60;
+/// Voice XP is opt-in, so guilds that haven't configured it don't earn any
+pub const DEFAULT_VOICE_XP_PER_MINUTE: i16 = 0;
+pub const DEFAULT_LEVEL_UP_EMBED_COLOR: u32 = 0x33_33_66;
+/// The level members can prestige at, once a guild turns prestige on
+pub const DEFAULT_PRESTIGE_LEVEL: i64 = 100;
+
+/// [`sqlx::query_as!`] a [`RawGuildConfig`], with all of its columns between `$before` and `$after`.
+///
+/// Use it like `query_guild_config!("SELECT ", " FROM guild_configs WHERE id = $1", id)`.
+/// sqlx needs the query as one literal to check it, so this is how the column list is kept in one place.
+#[macro_export]
+macro_rules! query_guild_config {
+    ($before:literal, $after:literal $(, $arg:expr)* $(,)?) => {
+        ::sqlx::query_as!(
+            $crate::RawGuildConfig,
+            $before
+                + "one_at_a_time, level_up_message, level_up_channel, ping_on_level_up, \
+                max_xp_per_message, min_xp_per_message, message_cooldown, xp_award_strategy, \
+                voice_xp_per_minute, level_up_embed, level_up_embed_title, level_up_embed_color, \
+                level_up_embed_thumbnail, level_curve, level_curve_xp, level_curve_growth, \
+                level_curve_table, level_curve_wall, level_curve_wall_start, level_curve_wall_slope, \
+                prestige, prestige_level, prestige_xp_multiplier"
+                + $after
+            $(, $arg)*
+        )
+    };
+}
 
 #[derive(Clone, Default)]
 pub struct RawGuildConfig {
@@ -176,17 +231,49 @@ pub struct RawGuildConfig {
     pub min_xp_per_message: Option<i16>,
     pub max_xp_per_message: Option<i16>,
     pub message_cooldown: Option<i16>,
+    pub xp_award_strategy: Option<i16>,
+    pub voice_xp_per_minute: Option<i16>,
+    pub level_up_embed: Option<bool>,
+    pub level_up_embed_title: Option<String>,
+    pub level_up_embed_color: Option<i32>,
+    pub level_up_embed_thumbnail: Option<bool>,
+    pub level_curve: Option<i16>,
+    pub level_curve_xp: Option<i64>,
+    pub level_curve_growth: Option<f64>,
+    pub level_curve_table: Option<Vec<i64>>,
+    pub level_curve_wall: Option<bool>,
+    pub level_curve_wall_start: Option<i64>,
+    pub level_curve_wall_slope: Option<i64>,
+    pub prestige: Option<bool>,
+    pub prestige_level: Option<i64>,
+    pub prestige_xp_multiplier: Option<f32>,
 }
 
 impl TryFrom<RawGuildConfig> for GuildConfig {
-    type Error = simpleinterpolation::Error;
+    type Error = GuildConfigError;
 
     fn try_from(value: RawGuildConfig) -> Result<Self, Self::Error> {
-        let level_up_message = if let Some(str) = value.level_up_message {
-            Some(Interpolation::new(str)?)
-        } else {
-            None
-        };
+        let level_up_message = value
+            .level_up_message
+            .and_then(|template| stored_template("level_up_message", &template));
+        let level_up_embed_title = value
+            .level_up_embed_title
+            .and_then(|template| stored_template("level_up_embed_title", &template));
+
+        let level_curve = value
+            .level_curve
+            .map(|kind| {
+                Curve::try_from(&RawLevelCurve {
+                    kind,
+                    xp: value.level_curve_xp,
+                    growth: value.level_curve_growth,
+                    table: value.level_curve_table,
+                    wall: value.level_curve_wall,
+                    wall_start: value.level_curve_wall_start,
+                    wall_slope: value.level_curve_wall_slope,
+                })
+            })
+            .transpose()?;
 
         let gc = Self {
             one_at_a_time: value.one_at_a_time,
@@ -196,11 +283,45 @@ impl TryFrom<RawGuildConfig> for GuildConfig {
             min_xp_per_message: value.min_xp_per_message,
             max_xp_per_message: value.max_xp_per_message,
             cooldown: value.message_cooldown,
+            xp_award_strategy: value
+                .xp_award_strategy
+                .map(XpAwardStrategy::try_from)
+                .transpose()?,
+            voice_xp_per_minute: value.voice_xp_per_minute,
+            level_up_embed: value.level_up_embed,
+            level_up_embed_title,
+            level_up_embed_color: value
+                .level_up_embed_color
+                .map(|color| color.reinterpret_bits()),
+            level_up_embed_thumbnail: value.level_up_embed_thumbnail,
+            level_curve,
+            prestige: value.prestige,
+            prestige_level: value.prestige_level,
+            prestige_xp_multiplier: value.prestige_xp_multiplier,
         };
         Ok(gc)
     }
 }
 
+/// Compile a template loaded from the database.
+///
+/// Older versions of the compiler allowed templates which no longer compile, and one bad
+/// template shouldn't break the rest of a guild's config, so those are treated as unset.
+pub fn stored_template(column: &'static str, template: &str) -> Option<Interpolation> {
+    match Interpolation::new(template) {
+        Ok(interpolation) => Some(interpolation),
+        Err(source) => {
+            warn!(
+                column,
+                template,
+                ?source,
+                "Ignoring invalid stored template"
+            );
+            None
+        }
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct GuildConfig {
     pub one_at_a_time: Option<bool>,
@@ -210,12 +331,228 @@ pub struct GuildConfig {
     pub min_xp_per_message: Option<i16>,
     pub max_xp_per_message: Option<i16>,
     pub cooldown: Option<i16>,
+    pub xp_award_strategy: Option<XpAwardStrategy>,
+    pub voice_xp_per_minute: Option<i16>,
+    /// Send level-up messages as an embed, rather than plain text
+    pub level_up_embed: Option<bool>,
+    pub level_up_embed_title: Option<Interpolation>,
+    pub level_up_embed_color: Option<u32>,
+    /// Show the member's avatar in level-up embeds
+    pub level_up_embed_thumbnail: Option<bool>,
+    /// How much XP each level takes, the MEE6 curve if unset
+    pub level_curve: Option<Curve>,
+    /// Let members reset their XP for a prestige tier once they reach `prestige_level`
+    pub prestige: Option<bool>,
+    pub prestige_level: Option<i64>,
+    /// Extra XP multiplier for each prestige tier a member has, so 1.1 gives tier 3 1.3x XP
+    pub prestige_xp_multiplier: Option<f32>,
+}
+
+impl GuildConfig {
+    #[must_use]
+    pub fn level_curve(&self) -> Cow<'_, Curve> {
+        self.level_curve
+            .as_ref()
+            .map_or_else(|| Cow::Owned(Curve::default()), Cow::Borrowed)
+    }
+
+    /// The level members can prestige at, or `None` if prestige is turned off
+    #[must_use]
+    pub fn prestige_level(&self) -> Option<i64> {
+        self.prestige
+            .is_some_and(|v| v)
+            .then(|| self.prestige_level.unwrap_or(DEFAULT_PRESTIGE_LEVEL))
+    }
+
+    /// What to multiply the XP of a member at prestige `tier` by
+    #[must_use]
+    pub fn prestige_multiplier(&self, tier: i64) -> f32 {
+        match self.prestige_xp_multiplier {
+            Some(multiplier) if self.prestige_level().is_some() => {
+                #[allow(clippy::cast_precision_loss)]
+                let tier = tier as f32;
+                (multiplier - 1.0).mul_add(tier, 1.0)
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GuildConfigError {
+    #[error("Invalid level-up message: {0}")]
+    Interpolation(#[from] simpleinterpolation::Error),
+    #[error("Unknown XP award strategy {0}")]
+    UnknownXpAwardStrategy(i16),
+    #[error("Invalid level curve {0}")]
+    InvalidLevelCurve(i16),
+}
+
+/// A level curve, as stored in the `level_curve*` columns of `guild_configs`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RawLevelCurve {
+    pub kind: i16,
+    /// XP per level for linear curves, or for the first level of exponential ones
+    pub xp: Option<i64>,
+    pub growth: Option<f64>,
+    pub table: Option<Vec<i64>>,
+    /// Whether MEE6 curves have an XP wall, which they do unless this is `Some(false)`
+    pub wall: Option<bool>,
+    /// The level MEE6 curves' XP wall starts at, 30 if unset
+    pub wall_start: Option<i64>,
+    /// XP per level past the wall. If unset, it's the XP needed to reach the wall
+    pub wall_slope: Option<i64>,
+}
+
+impl From<&Curve> for RawLevelCurve {
+    fn from(value: &Curve) -> Self {
+        match value {
+            Curve::Mee6(mee6) => mee6.wall().map_or_else(
+                || Self {
+                    wall: Some(false),
+                    ..Self::default()
+                },
+                |wall| Self {
+                    wall: Some(true),
+                    wall_start: Some(wall.start().reinterpret_bits()),
+                    wall_slope: wall.slope().map(|slope| slope.get().reinterpret_bits()),
+                    ..Self::default()
+                },
+            ),
+            Curve::Classic => Self {
+                kind: 1,
+                ..Self::default()
+            },
+            Curve::Linear(linear) => Self {
+                kind: 2,
+                xp: Some(linear.xp_per_level().get().reinterpret_bits()),
+                ..Self::default()
+            },
+            Curve::Exponential(exponential) => Self {
+                kind: 3,
+                xp: Some(exponential.first_level().get().reinterpret_bits()),
+                growth: Some(exponential.growth()),
+                ..Self::default()
+            },
+            Curve::Table(table) => Self {
+                kind: 4,
+                table: Some(
+                    table
+                        .levels()
+                        .iter()
+                        .map(ReinterpretPrimitiveBits::reinterpret_bits)
+                        .collect(),
+                ),
+                ..Self::default()
+            },
+        }
+    }
+}
+
+impl TryFrom<&RawLevelCurve> for Curve {
+    type Error = GuildConfigError;
+
+    fn try_from(value: &RawLevelCurve) -> Result<Self, Self::Error> {
+        let non_zero = |xp: i64| NonZeroU64::new(xp.reinterpret_bits());
+        let xp = value.xp.and_then(non_zero);
+        let curve = match value.kind {
+            0 => {
+                let wall = value.wall.unwrap_or(true).then(|| {
+                    Wall::new(
+                        value
+                            .wall_start
+                            .map_or(Wall::MEE6.start(), |start| start.reinterpret_bits()),
+                        value.wall_slope.and_then(non_zero),
+                    )
+                });
+                Some(Self::Mee6(Mee6::new(wall)))
+            }
+            1 => Some(Self::Classic),
+            2 => xp.map(|xp| Self::Linear(Linear::new(xp))),
+            3 => xp
+                .zip(value.growth)
+                .and_then(|(xp, growth)| Exponential::new(xp, growth))
+                .map(Self::Exponential),
+            4 => value
+                .table
+                .as_ref()
+                .and_then(|table| {
+                    Table::new(
+                        table
+                            .iter()
+                            .map(ReinterpretPrimitiveBits::reinterpret_bits)
+                            .collect(),
+                    )
+                })
+                .map(Self::Table),
+            _ => None,
+        };
+        curve.ok_or(GuildConfigError::InvalidLevelCurve(value.kind))
+    }
+}
+
+/// How a guild hands out XP for each message. Stored in the database as an `INT2`,
+/// where `NULL` means the default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum XpAwardStrategy {
+    /// Pick a uniformly random value between the minimum and maximum XP per message, like MEE6.
+    UniformRange,
+    /// Award `ceil(sqrt(characters / 10))`, ignoring the configured bounds.
+    /// The default, since it's what every guild earned before strategies existed.
+    #[default]
+    MessageLength,
+    /// Roll between the minimum and a ceiling that grows with message length, up to the maximum.
+    Hybrid,
+}
+
+impl XpAwardStrategy {
+    #[must_use]
+    pub const fn to_db(self) -> i16 {
+        match self {
+            Self::UniformRange => 0,
+            Self::MessageLength => 1,
+            Self::Hybrid => 2,
+        }
+    }
+}
+
+impl TryFrom<i16> for XpAwardStrategy {
+    type Error = GuildConfigError;
+
+    fn try_from(value: i16) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::UniformRange),
+            1 => Ok(Self::MessageLength),
+            2 => Ok(Self::Hybrid),
+            _ => Err(GuildConfigError::UnknownXpAwardStrategy(value)),
+        }
+    }
+}
+
+impl Display for XpAwardStrategy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::UniformRange => "random range",
+            Self::MessageLength => "message length",
+            Self::Hybrid => "hybrid",
+        };
+        f.write_str(name)
+    }
 }
 
+/// A role which is given, or taken away if `remove` is set, once a member reaches `requirement`.
 #[derive(Debug)]
 pub struct RoleReward {
     pub id: Id<RoleMarker>,
     pub requirement: i64,
+    pub remove: bool,
+}
+
+/// A role which is held by whoever is in the top `max_rank` of the leaderboard.
+#[derive(Debug)]
+pub struct RankReward {
+    pub id: Id<RoleMarker>,
+    pub max_rank: i64,
 }
 
 #[must_use]
@@ -224,6 +561,131 @@ pub fn sort_rewards(a: &RoleReward, b: &RoleReward) -> std::cmp::Ordering {
     a.requirement.cmp(&b.requirement)
 }
 
+/// The reward which decides whether a member at `level` should have `role`: the one
+/// with the highest requirement they meet. If a role is given and taken away at the
+/// same level, taking it away wins.
+fn deciding_reward(
+    rewards: &[RoleReward],
+    role: Id<RoleMarker>,
+    level: i64,
+) -> Option<&RoleReward> {
+    rewards
+        .iter()
+        .filter(|reward| reward.id == role && reward.requirement <= level)
+        .max_by_key(|reward| (reward.requirement, reward.remove))
+}
+
+/// The reward roles a member at `level` has earned. If `one_at_a_time` is set,
+/// only the roles from the highest level they have earned roles at are included.
+#[must_use]
+pub fn earned_reward_roles(
+    rewards: &[RoleReward],
+    level: i64,
+    one_at_a_time: bool,
+) -> Vec<Id<RoleMarker>> {
+    let earned: Vec<&RoleReward> = rewards
+        .iter()
+        .filter(|reward| !reward.remove && reward.requirement <= level)
+        .filter(|reward| {
+            deciding_reward(rewards, reward.id, level).is_some_and(|decider| !decider.remove)
+        })
+        .collect();
+    let highest = earned.iter().map(|reward| reward.requirement).max();
+    earned
+        .into_iter()
+        .filter(|reward| !one_at_a_time || Some(reward.requirement) == highest)
+        .map(|reward| reward.id)
+        .collect()
+}
+
+/// The changes needed to make a member's reward roles match their level.
+///
+/// Roles which aren't rewards are never touched, and roles which are only ever
+/// taken away are left alone until the member reaches that level.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RewardRoleChanges {
+    pub add: Vec<Id<RoleMarker>>,
+    pub remove: Vec<Id<RoleMarker>>,
+}
+
+impl RewardRoleChanges {
+    #[must_use]
+    pub fn new(
+        current: &[Id<RoleMarker>],
+        rewards: &[RoleReward],
+        level: i64,
+        one_at_a_time: bool,
+    ) -> Self {
+        let earned = earned_reward_roles(rewards, level, one_at_a_time);
+        let add = earned
+            .iter()
+            .filter(|role| !current.contains(role))
+            .copied()
+            .collect();
+        let remove = current
+            .iter()
+            .filter(|role| !earned.contains(role))
+            .filter(|role| {
+                let is_given = rewards
+                    .iter()
+                    .any(|reward| reward.id == **role && !reward.remove);
+                let is_taken = deciding_reward(rewards, **role, level).is_some_and(|r| r.remove);
+                is_given || is_taken
+            })
+            .copied()
+            .collect();
+        Self { add, remove }
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty()
+    }
+
+    /// Every role this change would add or remove
+    pub fn changed(&self) -> impl Iterator<Item = Id<RoleMarker>> + '_ {
+        self.add.iter().chain(self.remove.iter()).copied()
+    }
+
+    /// The role this change adds with the highest level requirement, which is the one
+    /// a level-up message should celebrate.
+    #[must_use]
+    pub fn newest_added(&self, rewards: &[RoleReward]) -> Option<Id<RoleMarker>> {
+        rewards
+            .iter()
+            .filter(|reward| !reward.remove && self.add.contains(&reward.id))
+            .max_by_key(|reward| reward.requirement)
+            .map(|reward| reward.id)
+    }
+
+    /// Combine two sets of changes, like level and prestige rewards, into one
+    #[must_use]
+    pub fn merge(mut self, other: Self) -> Self {
+        for role in other.add {
+            if !self.add.contains(&role) {
+                self.add.push(role);
+            }
+        }
+        for role in other.remove {
+            if !self.remove.contains(&role) && !self.add.contains(&role) {
+                self.remove.push(role);
+            }
+        }
+        self
+    }
+
+    /// The member's full role list after this change, for use with `update_guild_member`
+    #[must_use]
+    pub fn apply(&self, current: &[Id<RoleMarker>]) -> Vec<Id<RoleMarker>> {
+        current
+            .iter()
+            .filter(|role| !self.remove.contains(role))
+            .chain(self.add.iter())
+            .copied()
+            .collect()
+    }
+}
+
 #[inline]
 const fn tribool(data: Option<bool>, default: Option<bool>) -> &'static str {
     match (data, default) {
@@ -233,7 +695,7 @@ const fn tribool(data: Option<bool>, default: Option<bool>) -> &'static str {
     }
 }
 
-fn opt_code_str(data: Option<&str>) -> Cow<str> {
+fn opt_code_str(data: Option<&str>) -> Cow<'_, str> {
     data.map_or(Cow::Borrowed("unset"), |v| Cow::Owned(format!("`{v}`")))
 }
 
@@ -272,28 +734,379 @@ impl Display for GuildConfig {
             "Level-up channel: {}",
             opt_mention_str(self.level_up_channel, '#')
         )?;
+        let strategy = self.xp_award_strategy.unwrap_or_default();
+        let unused = if strategy == XpAwardStrategy::MessageLength {
+            " (unused by the message length strategy)"
+        } else {
+            ""
+        };
         writeln!(
             f,
-            "Maximum XP per message: {}",
+            "Maximum XP per message: {}{unused}",
             self.max_xp_per_message
                 .unwrap_or(DEFAULT_MAX_XP_PER_MESSAGE)
         )?;
         writeln!(
             f,
-            "Minimum XP per message: {}",
+            "Minimum XP per message: {}{unused}",
             self.min_xp_per_message
                 .unwrap_or(DEFAULT_MIN_XP_PER_MESSAGE)
         )?;
-        write!(
+        writeln!(f, "XP award strategy: {strategy}")?;
+        writeln!(
             f,
             "Cooldown (seconds): {}",
             self.cooldown.unwrap_or(DEFAULT_MESSAGE_COOLDOWN)
         )?;
+        writeln!(
+            f,
+            "Voice XP per minute: {}",
+            self.voice_xp_per_minute
+                .unwrap_or(DEFAULT_VOICE_XP_PER_MINUTE)
+        )?;
+        writeln!(f, "Level curve: {}", self.level_curve())?;
+        writeln!(f, "Prestige: {}", tribool(self.prestige, Some(false)))?;
+        writeln!(
+            f,
+            "Prestige level: {}",
+            self.prestige_level.unwrap_or(DEFAULT_PRESTIGE_LEVEL)
+        )?;
+        writeln!(
+            f,
+            "Prestige XP multiplier per tier: {}x",
+            self.prestige_xp_multiplier.unwrap_or(1.0)
+        )?;
+        writeln!(
+            f,
+            "Level-up embed: {}",
+            tribool(self.level_up_embed, Some(false))
+        )?;
+        writeln!(
+            f,
+            "Level-up embed title: {}",
+            opt_code_str(
+                self.level_up_embed_title
+                    .as_ref()
+                    .map(Interpolation::input_value)
+                    .as_deref()
+            )
+        )?;
+        writeln!(
+            f,
+            "Level-up embed color: #{:06X}",
+            self.level_up_embed_color
+                .unwrap_or(DEFAULT_LEVEL_UP_EMBED_COLOR)
+        )?;
+        write!(
+            f,
+            "Level-up embed avatar thumbnail: {}",
+            tribool(self.level_up_embed_thumbnail, Some(false))
+        )?;
         Ok(())
     }
 }
 
+/// Postgres channel the database announces changes to cached guild data on,
+/// with a [`CacheInvalidation`] as the payload.
+pub const CACHE_INVALIDATION_CHANNEL: &str = "xpd_cache_invalidation";
+
+/// Per-guild data which listeners cache, and have to reload when it changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CacheKind {
+    Config,
+    Rewards,
+    ChannelRules,
+    RoleRules,
+    LevelUpMessages,
+}
+
+impl CacheKind {
+    pub const ALL: [Self; 5] = [
+        Self::Config,
+        Self::Rewards,
+        Self::ChannelRules,
+        Self::RoleRules,
+        Self::LevelUpMessages,
+    ];
+
+    /// The name database triggers use for this cache
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Config => "config",
+            Self::Rewards => "rewards",
+            Self::ChannelRules => "channel_rules",
+            Self::RoleRules => "role_rules",
+            Self::LevelUpMessages => "level_up_messages",
+        }
+    }
+}
+
+/// One guild's cache going stale, sent as `<cache name>:<guild id>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheInvalidation {
+    pub kind: CacheKind,
+    pub guild: Id<GuildMarker>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid cache invalidation: {0:?}")]
+pub struct CacheInvalidationError(String);
+
+impl FromStr for CacheInvalidation {
+    type Err = CacheInvalidationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || CacheInvalidationError(s.to_owned());
+        let (name, guild) = s.split_once(':').ok_or_else(invalid)?;
+        let kind = CacheKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name)
+            .ok_or_else(invalid)?;
+        // the guild comes straight out of an INT8 column
+        let guild: i64 = guild.parse().map_err(|_| invalid())?;
+        let guild = Id::new_checked(guild.reinterpret_bits()).ok_or_else(invalid)?;
+        Ok(Self { kind, guild })
+    }
+}
+
+impl Display for CacheInvalidation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.kind.name(), id_to_db(self.guild))
+    }
+}
+
+/// Something which can tell listeners that their cached guild data is out of date.
+pub trait CacheInvalidator {
+    /// Make sure `guild`'s `kind` cache is reloaded from the database.
+    fn invalidate(
+        &self,
+        kind: CacheKind,
+        guild: Id<GuildMarker>,
+    ) -> impl Future<Output = ()> + Send;
+
+    /// Replace `guild`'s cached config with one which was just saved.
+    fn update_config(
+        &self,
+        guild: Id<GuildMarker>,
+        config: GuildConfig,
+    ) -> impl Future<Output = ()> + Send;
+}
+
 pub trait RequiredEvents {
     fn required_intents() -> Intents;
     fn required_events() -> EventTypeFlags;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: Id<RoleMarker> = Id::new(1);
+    const FIVE: Id<RoleMarker> = Id::new(5);
+    const TEN: Id<RoleMarker> = Id::new(10);
+
+    const NEWCOMER: Id<RoleMarker> = Id::new(2);
+    const ALSO_FIVE: Id<RoleMarker> = Id::new(55);
+
+    const fn give(id: Id<RoleMarker>, requirement: i64) -> RoleReward {
+        RoleReward {
+            id,
+            requirement,
+            remove: false,
+        }
+    }
+
+    const fn take(id: Id<RoleMarker>, requirement: i64) -> RoleReward {
+        RoleReward {
+            id,
+            requirement,
+            remove: true,
+        }
+    }
+
+    fn rewards() -> [RoleReward; 2] {
+        [give(TEN, 10), give(FIVE, 5)]
+    }
+
+    #[test]
+    fn invalid_stored_templates_are_unset() {
+        let config = GuildConfig::try_from(RawGuildConfig {
+            level_up_message: Some("GG {user_mention".to_string()),
+            level_up_embed_title: Some("Level {level}!".to_string()),
+            ..RawGuildConfig::default()
+        })
+        .unwrap();
+        assert!(config.level_up_message.is_none());
+        assert!(config.level_up_embed_title.is_some());
+    }
+
+    #[test]
+    fn earned_roles() {
+        assert!(earned_reward_roles(&rewards(), 4, false).is_empty());
+        assert_eq!(earned_reward_roles(&rewards(), 7, false), [FIVE]);
+        assert_eq!(earned_reward_roles(&rewards(), 12, false), [TEN, FIVE]);
+        assert_eq!(earned_reward_roles(&rewards(), 12, true), [TEN]);
+    }
+
+    #[test]
+    fn newest_added_role() {
+        let changes = RewardRoleChanges::new(&[], &rewards(), 12, false);
+        assert_eq!(changes.newest_added(&rewards()), Some(TEN));
+        let changes = RewardRoleChanges::new(&[TEN, FIVE], &rewards(), 12, false);
+        assert_eq!(changes.newest_added(&rewards()), None);
+    }
+
+    #[test]
+    fn merged_changes() {
+        let level = RewardRoleChanges {
+            add: vec![FIVE],
+            remove: vec![NEWCOMER],
+        };
+        let prestige = RewardRoleChanges {
+            add: vec![TEN, FIVE],
+            remove: vec![ALSO_FIVE, FIVE],
+        };
+        let merged = level.merge(prestige);
+        assert_eq!(merged.add, [FIVE, TEN]);
+        assert_eq!(merged.remove, [NEWCOMER, ALSO_FIVE]);
+    }
+
+    #[test]
+    fn prestige_multiplier() {
+        let mut config = GuildConfig {
+            prestige_xp_multiplier: Some(1.5),
+            ..GuildConfig::default()
+        };
+        assert!((config.prestige_multiplier(2) - 1.0).abs() < f32::EPSILON);
+        config.prestige = Some(true);
+        assert_eq!(config.prestige_level(), Some(DEFAULT_PRESTIGE_LEVEL));
+        assert!((config.prestige_multiplier(0) - 1.0).abs() < f32::EPSILON);
+        assert!((config.prestige_multiplier(2) - 2.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn adds_missing_rewards() {
+        let changes = RewardRoleChanges::new(&[BASE], &rewards(), 12, false);
+        assert_eq!(changes.add, [TEN, FIVE]);
+        assert!(changes.remove.is_empty());
+        assert_eq!(changes.apply(&[BASE]), [BASE, TEN, FIVE]);
+    }
+
+    #[test]
+    fn removes_unearned_rewards() {
+        let changes = RewardRoleChanges::new(&[BASE, FIVE, TEN], &rewards(), 6, false);
+        assert!(changes.add.is_empty());
+        assert_eq!(changes.remove, [TEN]);
+        assert_eq!(changes.apply(&[BASE, FIVE, TEN]), [BASE, FIVE]);
+
+        let changes = RewardRoleChanges::new(&[BASE, FIVE, TEN], &rewards(), 0, false);
+        assert_eq!(changes.apply(&[BASE, FIVE, TEN]), [BASE]);
+    }
+
+    #[test]
+    fn one_at_a_time_swaps_roles() {
+        let changes = RewardRoleChanges::new(&[FIVE], &rewards(), 10, true);
+        assert_eq!(changes.add, [TEN]);
+        assert_eq!(changes.remove, [FIVE]);
+    }
+
+    #[test]
+    fn several_roles_per_level() {
+        let rewards = [give(FIVE, 5), give(ALSO_FIVE, 5), give(TEN, 10)];
+        assert_eq!(earned_reward_roles(&rewards, 7, false), [FIVE, ALSO_FIVE]);
+        assert_eq!(earned_reward_roles(&rewards, 7, true), [FIVE, ALSO_FIVE]);
+        assert_eq!(earned_reward_roles(&rewards, 10, true), [TEN]);
+    }
+
+    #[test]
+    fn removal_roles() {
+        let rewards = [give(FIVE, 5), take(NEWCOMER, 5)];
+        // newcomer isn't ours to touch until level 5
+        assert!(RewardRoleChanges::new(&[BASE, NEWCOMER], &rewards, 2, false).is_empty());
+
+        let changes = RewardRoleChanges::new(&[BASE, NEWCOMER], &rewards, 5, false);
+        assert_eq!(changes.add, [FIVE]);
+        assert_eq!(changes.remove, [NEWCOMER]);
+        assert_eq!(changes.apply(&[BASE, NEWCOMER]), [BASE, FIVE]);
+    }
+
+    #[test]
+    fn given_then_taken_away() {
+        let rewards = [give(FIVE, 5), take(FIVE, 10)];
+        assert_eq!(earned_reward_roles(&rewards, 7, false), [FIVE]);
+        assert!(earned_reward_roles(&rewards, 10, false).is_empty());
+        let changes = RewardRoleChanges::new(&[FIVE], &rewards, 12, false);
+        assert_eq!(changes.remove, [FIVE]);
+        // dropping back below level 5 takes it away too
+        let changes = RewardRoleChanges::new(&[FIVE], &rewards, 3, false);
+        assert_eq!(changes.remove, [FIVE]);
+    }
+
+    #[test]
+    fn correct_roles_need_no_changes() {
+        assert!(RewardRoleChanges::new(&[BASE, FIVE], &rewards(), 9, false).is_empty());
+        assert!(RewardRoleChanges::new(&[BASE], &[], 100, false).is_empty());
+    }
+
+    #[test]
+    fn level_curves_round_trip() {
+        let xp = NonZeroU64::new(150).unwrap();
+        for curve in [
+            Curve::default(),
+            Curve::Mee6(Mee6::new(None)),
+            Curve::Mee6(Mee6::new(Some(Wall::new(50, None)))),
+            Curve::Mee6(Mee6::new(Some(Wall::new(20, Some(xp))))),
+            Curve::Classic,
+            Curve::Linear(Linear::new(xp)),
+            Curve::Exponential(Exponential::new(xp, 1.25).unwrap()),
+            Curve::Table(Table::new(vec![100, 300, 600]).unwrap()),
+        ] {
+            let raw = RawLevelCurve::from(&curve);
+            assert_eq!(Curve::try_from(&raw).unwrap(), curve);
+        }
+        let raw = |kind, table: Option<Vec<i64>>| RawLevelCurve {
+            kind,
+            table,
+            ..RawLevelCurve::default()
+        };
+        assert_eq!(Curve::try_from(&raw(0, None)).unwrap(), Curve::default());
+        assert!(Curve::try_from(&raw(2, None)).is_err());
+        assert!(Curve::try_from(&raw(4, Some(vec![300, 100]))).is_err());
+    }
+
+    #[test]
+    fn cache_invalidation_round_trip() {
+        for kind in CacheKind::ALL {
+            let invalidation = CacheInvalidation {
+                kind,
+                guild: Id::new(u64::MAX),
+            };
+            assert_eq!(
+                invalidation
+                    .to_string()
+                    .parse::<CacheInvalidation>()
+                    .unwrap(),
+                invalidation
+            );
+        }
+        let rewards: CacheInvalidation = "rewards:302094807046684672".parse().unwrap();
+        assert_eq!(rewards.kind, CacheKind::Rewards);
+        assert_eq!(rewards.guild, Id::new(302_094_807_046_684_672));
+    }
+
+    #[test]
+    fn invalid_cache_invalidations() {
+        for payload in [
+            "",
+            "rewards",
+            "rewards:",
+            "rewards:0",
+            "rewards:1x",
+            "xp:1",
+            ":1",
+        ] {
+            assert!(payload.parse::<CacheInvalidation>().is_err(), "{payload}");
+        }
+    }
+}
@@ -3,17 +3,23 @@
 #[macro_use]
 extern crate tracing;
 
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 use sqlx::PgPool;
-use tokio_util::task::TaskTracker;
+use tokio::task::JoinHandle;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
 use tracing::Level;
 use twilight_gateway::{
     error::ReceiveMessageErrorType, CloseFrame, Config, Event, EventTypeFlags, Intents,
-    MessageSender, Shard, StreamExt,
+    MessageSender, Session, Shard, StreamExt,
 };
 use twilight_http::Client as DiscordClient;
 use twilight_model::{
@@ -23,9 +29,11 @@ use twilight_model::{
         Id,
     },
 };
-use xpd_common::{id_to_db, RequiredEvents};
+use xpd_common::{id_to_db, CacheInvalidator, RequiredEvents};
 use xpd_listener::XpdListener;
-use xpd_slash::{InvalidateCache, UpdateChannels, XpdSlash};
+use xpd_slash::{PublicKey, UpdateChannels, XpdSlash};
+
+mod sessions;
 
 #[tokio::main]
 async fn main() {
@@ -54,6 +62,10 @@ async fn main() {
         .await
         .expect("Failed to run database migrations!");
 
+    let cluster = ShardCluster::from_env();
+    // cluster-wide work only happens in one process
+    let primary = cluster.as_ref().is_none_or(ShardCluster::is_primary);
+
     let client = Arc::new(DiscordClient::new(token.clone()));
     let intents = XpdListener::required_intents() | XpdSlash::required_intents() | Intents::GUILDS;
     let my_id = client
@@ -74,34 +86,63 @@ async fn main() {
     let task_tracker = TaskTracker::new();
 
     let (config_tx, mut config_rx) = tokio::sync::mpsc::channel(10);
-    let (rewards_tx, mut rewards_rx) = tokio::sync::mpsc::channel(10);
+    let (invalidate_tx, mut invalidate_rx) = tokio::sync::mpsc::channel(10);
 
     let listener = XpdListener::new(db.clone(), client.clone(), task_tracker.clone(), my_id);
+    let persist_cooldowns = std::env::var("PERSIST_COOLDOWNS").is_ok_and(|v| v == "true");
+    if persist_cooldowns {
+        if let Err(source) = listener.restore_cooldowns().await {
+            error!(?source, "Could not restore message cooldowns");
+        }
+    }
 
     let updating_listener = listener.clone();
     let config_update = tokio::spawn(async move {
         while let Some((guild, config)) = config_rx.recv().await {
-            if let Err(source) = updating_listener.update_config(guild, config) {
-                error!(?guild, ?source, "Unable to update config for guild");
-            }
+            updating_listener.update_config(guild, config).await;
         }
     });
 
     let updating_listener = listener.clone();
-    let rewards_update = tokio::spawn(async move {
-        while let Some(InvalidateCache(guild)) = rewards_rx.recv().await {
+    let cache_invalidation = tokio::spawn(async move {
+        while let Some((guild, kind)) = invalidate_rx.recv().await {
             let updating_listener = updating_listener.clone();
-            tokio::spawn(async move {
-                if let Err(source) = updating_listener.invalidate_rewards(guild).await {
-                    error!(?guild, ?source, "Unable to invalidate rewards for guild");
-                }
-            });
+            tokio::spawn(async move { updating_listener.invalidate(kind, guild).await });
         }
     });
 
+    let jobs_shutdown = CancellationToken::new();
+    let rank_rewards_listener = listener.clone();
+    let rank_rewards_token = jobs_shutdown.clone();
+    let rank_rewards = primary.then(|| {
+        tokio::spawn(async move {
+            rank_rewards_listener
+                .rank_rewards_job(rank_rewards_token)
+                .await;
+        })
+    });
+
+    // each process pays out the voice channels on its own shards
+    let voice_flush_listener = listener.clone();
+    let voice_flush_token = jobs_shutdown.clone();
+    let voice_flush = tokio::spawn(async move {
+        voice_flush_listener
+            .voice_flush_job(voice_flush_token)
+            .await;
+    });
+
+    // picks up changes made by other processes, or straight to the database
+    let invalidation_listener = listener.clone();
+    let invalidation_token = jobs_shutdown.clone();
+    let invalidation_job = tokio::spawn(async move {
+        invalidation_listener
+            .invalidation_job(invalidation_token)
+            .await;
+    });
+
     let update_channels = UpdateChannels {
         config: config_tx,
-        rewards: rewards_tx,
+        invalidate: invalidate_tx,
     };
 
     let slash = XpdSlash::new(
@@ -113,30 +154,83 @@ async fn main() {
         control_guild,
         owners,
         update_channels,
+        primary,
     )
     .await;
+
+    // discord sends interactions here instead of over the gateway, once it's set in the developer portal
+    let interactions_endpoint = std::env::var("INTERACTIONS_ADDR").ok().map(|addr| {
+        let addr: SocketAddr = addr
+            .parse()
+            .unwrap_or_else(|e| panic!("INTERACTIONS_ADDR could not be parsed: {e}"));
+        let key: PublicKey = xpd_common::parse_var("DISCORD_PUBLIC_KEY");
+        let endpoint_slash = slash.clone();
+        let endpoint_token = jobs_shutdown.clone();
+        tokio::spawn(async move {
+            endpoint_slash
+                .serve_interactions(addr, key, endpoint_token)
+                .await
+                .expect("Failed to serve interactions endpoint");
+        })
+    });
+    let (shard_range, shard_total) = if let Some(cluster) = cluster {
+        info!(range = ?cluster.range, total = cluster.total, "Running shard range");
+        (cluster.range, cluster.total)
+    } else {
+        let recommended = client
+            .gateway()
+            .authed()
+            .await
+            .expect("Failed to get recommended shard count")
+            .model()
+            .await
+            .expect("Failed to convert recommended shard count");
+        (0..recommended.shards, recommended.shards)
+    };
+    let shard_ids: Vec<ShardId> = shard_range
+        .clone()
+        .map(|number| ShardId::new(number, shard_total))
+        .collect();
+    let saved_sessions = sessions::take(&db, &shard_ids)
+        .await
+        .unwrap_or_else(|source| {
+            error!(?source, "Could not load saved gateway sessions");
+            HashMap::new()
+        });
+    // resumed shards aren't told who is already in voice, so pick up where the last process left off
+    if let Err(source) = listener
+        .restore_voice(&shard_ids, |id| saved_sessions.contains_key(&id))
+        .await
+    {
+        error!(?source, "Could not restore voice sessions");
+    }
     let config = Config::new(token.clone(), intents);
     let shards: Vec<Shard> =
-        twilight_gateway::create_recommended(&client, config, |_, builder| builder.build())
-            .await
-            .expect("Failed to create recommended shard count")
-            .collect();
+        twilight_gateway::create_iterator(shard_range, shard_total, config, |id, builder| {
+            match saved_sessions.get(&id) {
+                Some(session) => builder.session(session.clone()).build(),
+                None => builder.build(),
+            }
+        })
+        .collect();
     let senders: Vec<MessageSender> = shards.iter().map(Shard::sender).collect();
-    info!("Connecting to discord");
+    info!(resuming = saved_sessions.len(), "Connecting to discord");
 
     let shutdown = Arc::new(AtomicBool::new(false));
-    for shard in shards {
-        let client = client.clone();
-        task_tracker.clone().spawn(event_loop(
-            shard,
-            client,
-            task_tracker.clone(),
-            shutdown.clone(),
-            listener.clone(),
-            slash.clone(),
-            db.clone(),
-        ));
-    }
+    let shard_loops: Vec<JoinHandle<Option<(ShardId, Session)>>> = shards
+        .into_iter()
+        .map(|shard| {
+            task_tracker.spawn(event_loop(
+                shard,
+                client.clone(),
+                task_tracker.clone(),
+                shutdown.clone(),
+                listener.clone(),
+                slash.clone(),
+                db.clone(),
+            ))
+        })
+        .collect();
 
     vss::shutdown_signal().await;
     warn!("Shutting down..");
@@ -144,10 +238,26 @@ async fn main() {
     // Let the shards know not to reconnect
     shutdown.store(true, Ordering::Release);
 
+    jobs_shutdown.cancel();
+
     debug!("Informing discord of shutdown");
-    // Tell the shards to shut down
+    // Tell the shards to shut down, but keep their sessions open so the next process can resume them
     for sender in senders {
-        sender.close(CloseFrame::NORMAL).ok();
+        sender.close(CloseFrame::RESUME).ok();
+    }
+    let mut open_sessions = Vec::with_capacity(shard_loops.len());
+    for shard_loop in shard_loops {
+        match shard_loop.await {
+            Ok(session) => open_sessions.extend(session),
+            Err(source) => error!(?source, "Could not shut down shard"),
+        }
+    }
+
+    if let Some(interactions_endpoint) = interactions_endpoint {
+        debug!("Waiting for interactions endpoint to finish responding");
+        if let Err(source) = interactions_endpoint.await {
+            error!(?source, "Could not shut down interactions endpoint");
+        }
     }
 
     debug!("Waiting for background tasks to complete");
@@ -155,13 +265,46 @@ async fn main() {
     task_tracker.close();
     task_tracker.wait().await;
 
+    if let Err(source) = voice_flush.await {
+        error!(?source, "Could not shut down voice flush job");
+    }
+    debug!("Paying out voice XP");
+    if let Err(source) = listener.flush_voice().await {
+        error!(?source, "Could not pay out voice XP");
+    }
+
+    debug!("Saving voice sessions");
+    if let Err(source) = listener.save_voice().await {
+        error!(?source, "Could not save voice sessions");
+    }
+
+    debug!(count = open_sessions.len(), "Saving gateway sessions");
+    if let Err(source) = sessions::save(&db, &open_sessions).await {
+        error!(?source, "Could not save gateway sessions");
+    }
+
+    if persist_cooldowns {
+        debug!("Saving message cooldowns");
+        if let Err(source) = listener.save_cooldowns().await {
+            error!(?source, "Could not save message cooldowns");
+        }
+    }
+
     drop(slash); // Must be dropped before awaiting config shutdown, to allow the recv loop to end
     debug!("Waiting for listener updater to close");
     if let Err(source) = config_update.await {
         error!(?source, "Could not shut down config updater");
     }
-    if let Err(source) = rewards_update.await {
-        error!(?source, "Could not shut down config updater");
+    if let Err(source) = cache_invalidation.await {
+        error!(?source, "Could not shut down cache invalidator");
+    }
+    if let Some(rank_rewards) = rank_rewards {
+        if let Err(source) = rank_rewards.await {
+            error!(?source, "Could not shut down rank rewards job");
+        }
+    }
+    if let Err(source) = invalidation_job.await {
+        error!(?source, "Could not shut down cache invalidation job");
     }
 
     info!("Done, see ya!");
@@ -175,11 +318,14 @@ async fn event_loop(
     listener: XpdListener,
     slash: XpdSlash,
     db: PgPool,
-) {
+) -> Option<(ShardId, Session)> {
     let event_flags = XpdListener::required_events()
         | XpdSlash::required_events()
         | EventTypeFlags::READY
+        | EventTypeFlags::RESUMED
         | EventTypeFlags::GUILD_CREATE;
+    // discord doesn't send `GUILD_CREATE`s to resumed sessions, until they have to start over
+    let mut resumed = shard.session().is_some();
     while let Some(next) = shard.next_event(event_flags).await {
         trace!(?next, "got new event");
         let event = match next {
@@ -197,27 +343,85 @@ async fn event_loop(
         if matches!(event, Event::GatewayClose(_)) && shutdown.load(Ordering::Acquire) {
             break;
         }
+        match event {
+            Event::Resumed => info!(shard_id = ?shard.id(), "shard resumed session"),
+            Event::Ready(_) => resumed = false,
+            _ => {}
+        }
         trace!(?event, "got event");
         let listener = listener.clone();
         let http = http.clone();
         let slash = slash.clone();
         let db = db.clone();
         task_tracker.spawn(async move {
-            if let Err(error) = handle_event(event, http, listener, slash, db).await {
+            if let Err(error) = handle_event(event, resumed, http, listener, slash, db).await {
                 // this includes even user caused errors. User beware. Don't set up automatic emails or anything.
                 error!(?error, "Handler error");
             }
         });
     }
+    // closing with `CloseFrame::RESUME` leaves the session open for the next process
+    shard.session().map(|session| (shard.id(), session.clone()))
+}
+
+/// The shards this process runs, when several gateway processes split them.
+struct ShardCluster {
+    range: Range<u32>,
+    total: u32,
+}
+
+impl ShardCluster {
+    /// Reads `SHARD_RANGE` (like `0..16`, end exclusive) and `SHARD_TOTAL`,
+    /// or returns `None` to run discord's recommended number of shards ourselves.
+    /// # Panics
+    /// If only one of them is set, or they don't describe shards which exist
+    fn from_env() -> Option<Self> {
+        let (range, total) = match (std::env::var("SHARD_RANGE"), std::env::var("SHARD_TOTAL")) {
+            (Err(_), Err(_)) => return None,
+            (Ok(range), Ok(total)) => (range, total),
+            _ => panic!("SHARD_RANGE and SHARD_TOTAL must be set together"),
+        };
+        let total: u32 = total
+            .parse()
+            .unwrap_or_else(|e| panic!("SHARD_TOTAL could not be parsed: {e}"));
+        let (start, end) = range
+            .split_once("..")
+            .expect("SHARD_RANGE must look like start..end");
+        let [start, end]: [u32; 2] = [start, end].map(|bound| {
+            bound
+                .trim()
+                .parse()
+                .unwrap_or_else(|e| panic!("SHARD_RANGE could not be parsed: {e}"))
+        });
+        assert!(
+            start < end && end <= total,
+            "SHARD_RANGE must contain at least one shard, and none past SHARD_TOTAL"
+        );
+        Some(Self {
+            range: start..end,
+            total,
+        })
+    }
+
+    /// The process running shard 0 does cluster-wide work, like registering commands
+    const fn is_primary(&self) -> bool {
+        self.range.start == 0
+    }
 }
 
 async fn handle_event(
     event: Event,
+    resumed: bool,
     http: Arc<DiscordClient>,
     listener: XpdListener,
     slash: XpdSlash,
     db: PgPool,
 ) -> Result<(), Error> {
+    if resumed && !matches!(event, Event::GuildCreate(_) | Event::GuildDelete(_)) {
+        if let Some(guild_id) = event.guild_id() {
+            listener.ensure_cached(guild_id).await?;
+        }
+    }
     listener.update_cache(&event);
     match event {
         Event::Ready(ready) => {
@@ -247,7 +451,16 @@ async fn handle_event(
                 http.leave_guild(guild_add.id).await?;
                 return Ok(());
             }
+            listener.voice_guild_create(&guild_add).await?;
+        }
+        Event::GuildDelete(guild_delete) => {
+            listener.voice_guild_delete(guild_delete.id)?;
+            // outages also delete guilds, but those come back with everyone's cooldowns intact
+            if !guild_delete.unavailable {
+                listener.cooldown_guild_delete(guild_delete.id)?;
+            }
         }
+        Event::VoiceStateUpdate(voice_state) => listener.voice_state_update(voice_state.0).await?,
         Event::InteractionCreate(interaction_create) => slash.execute(*interaction_create).await,
         _ => {}
     };
@@ -0,0 +1,58 @@
+//! Gateway sessions are saved on shutdown, so the next process can resume them
+//! instead of missing every event sent while we restart.
+
+use std::collections::HashMap;
+
+use sqlx::PgPool;
+use twilight_gateway::Session;
+use twilight_model::gateway::ShardId;
+use xpd_common::ReinterpretPrimitiveBits;
+
+use crate::Error;
+
+/// Take the saved sessions for these shards, which must all have the same total.
+/// Sessions saved too long ago, or with a different shard total, are thrown out.
+pub async fn take(db: &PgPool, shards: &[ShardId]) -> Result<HashMap<ShardId, Session>, Error> {
+    let Some(total) = shards.first().map(|id| id.total()) else {
+        return Ok(HashMap::new());
+    };
+    let numbers: Vec<i64> = shards.iter().map(|id| i64::from(id.number())).collect();
+    // discord doesn't say how long sessions last, but resuming stale ones costs a few seconds
+    let sessions = sqlx::query!(
+        "DELETE FROM gateway_sessions WHERE shard = ANY($1) \
+            RETURNING shard, total, session_id, sequence, \
+            saved_at > NOW() - INTERVAL '5 minutes' AS \"fresh!\"",
+        &numbers
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .filter(|row| row.fresh && row.total == i64::from(total))
+    .filter_map(|row| {
+        let id = ShardId::new_checked(row.shard.try_into().ok()?, total)?;
+        let session = Session::new(row.sequence.reinterpret_bits(), row.session_id);
+        Some((id, session))
+    })
+    .collect();
+    Ok(sessions)
+}
+
+/// Save sessions left open by closing shards with [`CloseFrame::RESUME`](twilight_gateway::CloseFrame::RESUME).
+pub async fn save(db: &PgPool, sessions: &[(ShardId, Session)]) -> Result<(), Error> {
+    for (id, session) in sessions {
+        sqlx::query!(
+            "INSERT INTO gateway_sessions (shard, total, session_id, sequence) \
+                VALUES ($1, $2, $3, $4) \
+                ON CONFLICT (shard) DO UPDATE SET total = excluded.total, \
+                session_id = excluded.session_id, sequence = excluded.sequence, \
+                saved_at = excluded.saved_at",
+            i64::from(id.number()),
+            i64::from(id.total()),
+            session.id(),
+            session.sequence().reinterpret_bits()
+        )
+        .execute(db)
+        .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use rand::{seq::SliceRandom, Rng};
+use simpleinterpolation::{filters, Filters, Interpolation};
+use twilight_model::id::{
+    marker::{ChannelMarker, RoleMarker, UserMarker},
+    Id,
+};
+
+/// A guild's extra level-up messages, on top of [`xpd_common::GuildConfig::level_up_message`].
+#[derive(Debug, Default)]
+pub struct LevelUpMessages {
+    /// Messages which replace all others at a specific level
+    pub overrides: HashMap<i64, Interpolation>,
+    /// Messages picked at random, alongside the guild's normal level-up message
+    pub variants: Vec<Interpolation>,
+}
+
+impl LevelUpMessages {
+    /// Choose the template to congratulate a member who just reached `level` with.
+    /// Returns `None` if the guild has no level-up messages at all.
+    pub fn pick<'a, R: Rng + ?Sized>(
+        &'a self,
+        level: i64,
+        default: Option<&'a Interpolation>,
+        rng: &mut R,
+    ) -> Option<&'a Interpolation> {
+        if let Some(template) = self.overrides.get(&level) {
+            return Some(template);
+        }
+        let pool: Vec<&Interpolation> = default.into_iter().chain(&self.variants).collect();
+        pool.choose(rng).copied()
+    }
+}
+
+/// The filters level-up messages can use, see [`xpd_common::TEMPLATE_FILTERS`].
+pub fn level_up_filters() -> Filters {
+    let mut filters = Filters::new();
+    filters
+        .register("humanize", filters::humanize)
+        .register("ordinal", filters::ordinal)
+        .register("upper", filters::upper)
+        .register("lower", filters::lower);
+    filters
+}
+
+/// Everything a level-up message can mention, see [`xpd_common::TEMPLATE_VARIABLES`].
+#[derive(Debug)]
+pub struct LevelUpVariables<'a> {
+    pub user_id: Id<UserMarker>,
+    pub user_name: &'a str,
+    /// Server nickname, then global display name, then username
+    pub display_name: &'a str,
+    pub level: i64,
+    pub old_level: i64,
+    pub xp: u64,
+    /// Total XP at which the member reaches their next level, if the curve has one
+    pub next_level_xp: Option<u64>,
+    /// Prestige tier, 0 if the member hasn't prestiged
+    pub prestige: i64,
+    /// Only looked up when a template uses it
+    pub rank: Option<i64>,
+    pub guild_name: Option<String>,
+    /// The channel the member leveled up in, which isn't always where they get congratulated
+    pub channel_id: Id<ChannelMarker>,
+    /// The reward role the member was just given, if any
+    pub reward_role: Option<Id<RoleMarker>>,
+}
+
+impl LevelUpVariables<'_> {
+    /// Variables which have no value return `None`, so they render as nothing.
+    pub fn get(&self, name: &str) -> Option<Cow<'_, str>> {
+        let value = match name {
+            "user_mention" => format!("<@{}>", self.user_id).into(),
+            "user_name" => self.user_name.into(),
+            "display_name" => self.display_name.into(),
+            "level" => self.level.to_string().into(),
+            "old_level" => self.old_level.to_string().into(),
+            "xp" => self.xp.to_string().into(),
+            "next_level_xp" => self.next_level_xp?.to_string().into(),
+            "rank" => self.rank?.to_string().into(),
+            "prestige" => self.prestige.to_string().into(),
+            "guild_name" => self.guild_name.as_deref()?.into(),
+            "channel_mention" => format!("<#{}>", self.channel_id).into(),
+            "reward_role" => format!("<@&{}>", self.reward_role?).into(),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    pub fn render(&self, template: &Interpolation, filters: &Filters) -> String {
+        let mut output = String::new();
+        // writing to a String never fails
+        let _ = template.render_to(&mut output, &|name: &str| self.get(name), filters);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    fn template(text: &str) -> Interpolation {
+        Interpolation::new(text).unwrap()
+    }
+
+    #[test]
+    fn overrides_win() {
+        let messages = LevelUpMessages {
+            overrides: HashMap::from([(10, template("ten!"))]),
+            variants: vec![template("variant")],
+        };
+        let default = template("default");
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for _ in 0..100 {
+            let picked = messages.pick(10, Some(&default), &mut rng).unwrap();
+            assert_eq!(picked.input_value(), "ten!");
+        }
+    }
+
+    #[test]
+    fn variants_include_default() {
+        let messages = LevelUpMessages {
+            overrides: HashMap::from([(10, template("ten!"))]),
+            variants: vec![template("variant")],
+        };
+        let default = template("default");
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let picked: Vec<String> = (0..100)
+            .map(|_| {
+                messages
+                    .pick(5, Some(&default), &mut rng)
+                    .unwrap()
+                    .input_value()
+            })
+            .collect();
+        assert!(picked.iter().any(|v| v == "default"));
+        assert!(picked.iter().any(|v| v == "variant"));
+    }
+
+    fn variables() -> LevelUpVariables<'static> {
+        LevelUpVariables {
+            user_id: Id::new(1),
+            user_name: "valkyrie_pilot",
+            display_name: "Valk",
+            level: 5,
+            old_level: 4,
+            xp: 500,
+            next_level_xp: Some(600),
+            prestige: 1,
+            rank: Some(3),
+            guild_name: Some("experienced".to_string()),
+            channel_id: Id::new(2),
+            reward_role: Some(Id::new(3)),
+        }
+    }
+
+    #[test]
+    fn every_variable_has_a_value() {
+        let vars = variables();
+        for name in xpd_common::TEMPLATE_VARIABLES {
+            assert!(vars.get(name).is_some(), "{name} is not rendered");
+        }
+        assert!(vars.get("levle").is_none());
+    }
+
+    #[test]
+    fn missing_variables_render_empty() {
+        let vars = LevelUpVariables {
+            reward_role: None,
+            ..variables()
+        };
+        let filters = Filters::new();
+        let rendered = vars.render(
+            &template("{display_name} reached level {level}{reward_role}!"),
+            &filters,
+        );
+        assert_eq!(rendered, "Valk reached level 5!");
+        let rendered = variables().render(
+            &template("{reward_role} for {user_mention} in {channel_mention}"),
+            &filters,
+        );
+        assert_eq!(rendered, "<@&3> for <@1> in <#2>");
+    }
+
+    #[test]
+    fn every_filter_is_registered() {
+        let filters = level_up_filters();
+        for name in xpd_common::TEMPLATE_FILTERS {
+            assert!(filters.contains(name), "{name} is not registered");
+        }
+        let rendered = variables().render(
+            &template("{display_name:upper} is {rank:ordinal} with {xp:humanize} xp"),
+            &filters,
+        );
+        assert_eq!(rendered, "VALK is 3rd with 500 xp");
+    }
+
+    #[test]
+    fn nothing_configured() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        assert!(LevelUpMessages::default().pick(5, None, &mut rng).is_none());
+    }
+}
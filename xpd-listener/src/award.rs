@@ -0,0 +1,196 @@
+use rand::Rng;
+use xpd_common::{
+    GuildConfig, XpAwardStrategy, DEFAULT_MAX_XP_PER_MESSAGE, DEFAULT_MIN_XP_PER_MESSAGE,
+};
+
+/// A way of deciding how much XP a single message is worth.
+pub trait XpAward {
+    fn award<R: Rng + ?Sized>(&self, content: &str, rng: &mut R) -> i64;
+}
+
+/// MEE6-style uniformly random XP between the guild's minimum and maximum.
+#[derive(Clone, Copy, Debug)]
+pub struct UniformRange {
+    min: i64,
+    max: i64,
+}
+
+impl UniformRange {
+    /// Create a new range. If `min` is larger than `max`, `max` wins.
+    pub fn new(min: i64, max: i64) -> Self {
+        Self {
+            min: min.min(max),
+            max,
+        }
+    }
+}
+
+impl XpAward for UniformRange {
+    fn award<R: Rng + ?Sized>(&self, _content: &str, rng: &mut R) -> i64 {
+        rng.gen_range(self.min..=self.max)
+    }
+}
+
+/// The original experienced formula, `ceil(sqrt(characters / 10))`.
+#[derive(Clone, Copy, Debug)]
+pub struct MessageLength;
+
+impl XpAward for MessageLength {
+    fn award<R: Rng + ?Sized>(&self, content: &str, _rng: &mut R) -> i64 {
+        ((content.chars().count() as f64) / 10.0).sqrt().ceil() as i64
+    }
+}
+
+/// How many characters a message needs for [`Hybrid`] to be able to roll the maximum
+const HYBRID_FULL_LENGTH: i64 = 500;
+
+/// Rolls like [`UniformRange`], but the highest possible roll grows from the minimum to the
+/// maximum as the message grows to [`HYBRID_FULL_LENGTH`] characters. Short messages
+/// always earn the minimum, and only long ones can reach the maximum.
+#[derive(Clone, Copy, Debug)]
+pub struct Hybrid(UniformRange);
+
+impl XpAward for Hybrid {
+    fn award<R: Rng + ?Sized>(&self, content: &str, rng: &mut R) -> i64 {
+        let UniformRange { min, max } = self.0;
+        let length = i64::try_from(content.chars().count())
+            .unwrap_or(i64::MAX)
+            .min(HYBRID_FULL_LENGTH);
+        let ceiling = min + (max - min) * length / HYBRID_FULL_LENGTH;
+        UniformRange::new(min, ceiling).award(content, rng)
+    }
+}
+
+/// Calculate the XP a message is worth using the guild's chosen [`XpAwardStrategy`].
+pub fn message_xp<R: Rng + ?Sized>(config: &GuildConfig, content: &str, rng: &mut R) -> i64 {
+    let range = UniformRange::new(
+        config
+            .min_xp_per_message
+            .unwrap_or(DEFAULT_MIN_XP_PER_MESSAGE)
+            .into(),
+        config
+            .max_xp_per_message
+            .unwrap_or(DEFAULT_MAX_XP_PER_MESSAGE)
+            .into(),
+    );
+    match config.xp_award_strategy.unwrap_or_default() {
+        XpAwardStrategy::UniformRange => range.award(content, rng),
+        XpAwardStrategy::MessageLength => MessageLength.award(content, rng),
+        XpAwardStrategy::Hybrid => Hybrid(range).award(content, rng),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    const SHORT: &str = "hi";
+    const LONG: &str = "this message is long enough that the length formula alone would award a lot of \
+                        experience, certainly more than the minimum, but it should still never go past \
+                        the configured maximum no matter how much the user rambles on and on and on.";
+
+    fn config(strategy: XpAwardStrategy, min: i16, max: i16) -> GuildConfig {
+        GuildConfig {
+            min_xp_per_message: Some(min),
+            max_xp_per_message: Some(max),
+            xp_award_strategy: Some(strategy),
+            ..GuildConfig::default()
+        }
+    }
+
+    fn assert_bounds(strategy: XpAwardStrategy, min: i16, max: i16) {
+        let config = config(strategy, min, max);
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for content in [SHORT, LONG] {
+            for _ in 0..1000 {
+                let xp = message_xp(&config, content, &mut rng);
+                assert!(
+                    (i64::from(min)..=i64::from(max)).contains(&xp),
+                    "{strategy} awarded {xp}, outside of {min}..={max}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn uniform_respects_bounds() {
+        assert_bounds(XpAwardStrategy::UniformRange, 15, 25);
+        assert_bounds(XpAwardStrategy::UniformRange, 0, 1);
+        assert_bounds(XpAwardStrategy::UniformRange, 7, 7);
+    }
+
+    #[test]
+    fn hybrid_respects_bounds() {
+        assert_bounds(XpAwardStrategy::Hybrid, 15, 25);
+        assert_bounds(XpAwardStrategy::Hybrid, 1, 3);
+        assert_bounds(XpAwardStrategy::Hybrid, 7, 7);
+    }
+
+    #[test]
+    fn uniform_hits_both_ends() {
+        let config = config(XpAwardStrategy::UniformRange, 15, 25);
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let rolls: Vec<i64> = (0..1000)
+            .map(|_| message_xp(&config, SHORT, &mut rng))
+            .collect();
+        assert!(rolls.contains(&15));
+        assert!(rolls.contains(&25));
+    }
+
+    #[test]
+    fn hybrid_short_message_gets_minimum() {
+        let config = config(XpAwardStrategy::Hybrid, 5, 25);
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for _ in 0..100 {
+            assert_eq!(message_xp(&config, SHORT, &mut rng), 5);
+        }
+    }
+
+    #[test]
+    fn hybrid_long_message_can_beat_minimum() {
+        let config = config(XpAwardStrategy::Hybrid, 15, 25);
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let long = "a".repeat(2000);
+        let rolls: Vec<i64> = (0..1000)
+            .map(|_| message_xp(&config, &long, &mut rng))
+            .collect();
+        assert!(rolls.iter().any(|xp| *xp > 15));
+        assert!(rolls.contains(&25));
+        // halfway to full length, so halfway to the maximum
+        let medium = "a".repeat(250);
+        for _ in 0..1000 {
+            assert!(message_xp(&config, &medium, &mut rng) <= 20);
+        }
+    }
+
+    #[test]
+    fn message_length_matches_formula() {
+        let config = config(XpAwardStrategy::MessageLength, 15, 25);
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        assert_eq!(message_xp(&config, SHORT, &mut rng), 1);
+        assert_eq!(message_xp(&config, &"a".repeat(250), &mut rng), 5);
+    }
+
+    #[test]
+    fn inverted_bounds_use_max() {
+        let config = config(XpAwardStrategy::UniformRange, 30, 10);
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for _ in 0..100 {
+            assert_eq!(message_xp(&config, SHORT, &mut rng), 10);
+        }
+    }
+
+    #[test]
+    fn defaults_to_message_length() {
+        let config = GuildConfig::default();
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for content in [SHORT, LONG] {
+            assert_eq!(
+                message_xp(&config, content, &mut rng),
+                MessageLength.award(content, &mut rng)
+            );
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ahash::AHashMap;
+use sqlx::query;
+use twilight_model::id::{
+    marker::{GuildMarker, UserMarker},
+    Id,
+};
+use xpd_common::{db_to_id, id_to_db};
+
+use crate::{Error, XpdListenerInner};
+
+/// Seconds between the Unix epoch and the Discord epoch
+const DISCORD_EPOCH_SECS: u64 = 1_420_070_400;
+/// Guilds with fewer cooldowns than this are never swept
+const MIN_SWEEP_LEN: usize = 64;
+
+/// A member's cooldown which is still running, for saving across restarts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cooldown {
+    pub guild: Id<GuildMarker>,
+    pub user: Id<UserMarker>,
+    /// When the member can earn XP again, in seconds since the Discord epoch
+    pub expires: i64,
+}
+
+/// Message cooldowns for every guild, each behind its own lock.
+///
+/// Timestamps are seconds since the Discord epoch, like [`xpd_common::snowflake_to_timestamp`].
+/// Expired cooldowns are swept out whenever a guild's map doubles in size since its last sweep,
+/// so each guild holds at most about twice as many cooldowns as are actually running.
+#[derive(Debug, Default)]
+pub struct CooldownStore {
+    guilds: RwLock<HashMap<Id<GuildMarker>, Mutex<GuildCooldowns>>>,
+}
+
+#[derive(Debug)]
+struct GuildCooldowns {
+    expiries: AHashMap<Id<UserMarker>, i64>,
+    sweep_at: usize,
+}
+
+impl GuildCooldowns {
+    fn sweep(&mut self, now: i64) {
+        self.expiries.retain(|_, expires| *expires > now);
+        self.sweep_at = (self.expiries.len() * 2).max(MIN_SWEEP_LEN);
+    }
+}
+
+impl CooldownStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a `cooldown` second cooldown for this member at `now`, unless one is already
+    /// running. Returns `false` if they are still cooling down, and shouldn't earn XP.
+    pub fn try_start(
+        &self,
+        guild: Id<GuildMarker>,
+        user: Id<UserMarker>,
+        now: i64,
+        cooldown: i64,
+    ) -> Result<bool, Error> {
+        self.with_guild(guild, |cooldowns| {
+            if cooldowns
+                .expiries
+                .get(&user)
+                .is_some_and(|expires| *expires > now)
+            {
+                return false;
+            }
+            cooldowns.expiries.insert(user, now + cooldown);
+            if cooldowns.expiries.len() >= cooldowns.sweep_at {
+                cooldowns.sweep(now);
+            }
+            true
+        })
+    }
+
+    /// Forget every cooldown in a guild, for when we leave it.
+    pub fn remove_guild(&self, guild: Id<GuildMarker>) -> Result<(), Error> {
+        self.guilds.write()?.remove(&guild);
+        Ok(())
+    }
+
+    /// Every cooldown which is still running at `now`.
+    pub fn snapshot(&self, now: i64) -> Result<Vec<Cooldown>, Error> {
+        let guilds = self.guilds.read()?;
+        let mut running = Vec::new();
+        for (guild, cooldowns) in guilds.iter() {
+            let cooldowns = cooldowns.lock()?;
+            running.extend(
+                cooldowns
+                    .expiries
+                    .iter()
+                    .filter(|(_, expires)| **expires > now)
+                    .map(|(user, expires)| Cooldown {
+                        guild: *guild,
+                        user: *user,
+                        expires: *expires,
+                    }),
+            );
+        }
+        Ok(running)
+    }
+
+    /// Load cooldowns from a [`snapshot`](Self::snapshot), skipping any which ended before `now`.
+    /// Cooldowns already in the store win over restored ones.
+    pub fn restore(
+        &self,
+        cooldowns: impl IntoIterator<Item = Cooldown>,
+        now: i64,
+    ) -> Result<(), Error> {
+        for cooldown in cooldowns.into_iter().filter(|c| c.expires > now) {
+            self.with_guild(cooldown.guild, |guild| {
+                guild
+                    .expiries
+                    .entry(cooldown.user)
+                    .or_insert(cooldown.expires);
+            })?;
+        }
+        Ok(())
+    }
+
+    fn with_guild<T>(
+        &self,
+        guild: Id<GuildMarker>,
+        f: impl FnOnce(&mut GuildCooldowns) -> T,
+    ) -> Result<T, Error> {
+        // most messages come from guilds we've seen before, which only needs the read lock
+        if let Some(cooldowns) = self.guilds.read()?.get(&guild) {
+            return Ok(f(&mut *cooldowns.lock()?));
+        }
+        let mut guilds = self.guilds.write()?;
+        let cooldowns = guilds.entry(guild).or_insert_with(|| {
+            Mutex::new(GuildCooldowns {
+                expiries: AHashMap::new(),
+                sweep_at: MIN_SWEEP_LEN,
+            })
+        });
+        Ok(f(cooldowns.get_mut()?))
+    }
+}
+
+/// The current time in seconds since the Discord epoch
+pub fn discord_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| {
+            since.as_secs().saturating_sub(DISCORD_EPOCH_SECS)
+        })
+        .try_into()
+        .unwrap_or(i64::MAX)
+}
+
+impl XpdListenerInner {
+    /// Forget a guild's message cooldowns once we've been removed from it.
+    pub fn cooldown_guild_delete(&self, guild: Id<GuildMarker>) -> Result<(), Error> {
+        self.cooldowns.remove_guild(guild)
+    }
+
+    /// Save every running message cooldown, so a restart doesn't let members farm XP.
+    pub async fn save_cooldowns(&self) -> Result<(), Error> {
+        let cooldowns = self.cooldowns.snapshot(discord_now())?;
+        let (guilds, (users, expiries)): (Vec<i64>, (Vec<i64>, Vec<i64>)) = cooldowns
+            .iter()
+            .map(|c| (id_to_db(c.guild), (id_to_db(c.user), c.expires)))
+            .unzip();
+        // several processes may share this table, so merge instead of replacing it
+        query!(
+            "INSERT INTO message_cooldowns (guild, id, expires) \
+                SELECT * FROM UNNEST($1::INT8[], $2::INT8[], $3::INT8[]) \
+                ON CONFLICT (guild, id) DO UPDATE \
+                SET expires = GREATEST(message_cooldowns.expires, excluded.expires)",
+            &guilds,
+            &users,
+            &expiries
+        )
+        .execute(&self.db)
+        .await?;
+        debug!(count = cooldowns.len(), "Saved message cooldowns");
+        Ok(())
+    }
+
+    /// Load message cooldowns saved by [`save_cooldowns`](Self::save_cooldowns).
+    pub async fn restore_cooldowns(&self) -> Result<(), Error> {
+        let now = discord_now();
+        query!("DELETE FROM message_cooldowns WHERE expires <= $1", now)
+            .execute(&self.db)
+            .await?;
+        let cooldowns: Vec<Cooldown> = query!("SELECT guild, id, expires FROM message_cooldowns")
+            .fetch_all(&self.db)
+            .await?
+            .into_iter()
+            .map(|row| Cooldown {
+                guild: db_to_id(row.guild),
+                user: db_to_id(row.id),
+                expires: row.expires,
+            })
+            .collect();
+        debug!(count = cooldowns.len(), "Restored message cooldowns");
+        self.cooldowns.restore(cooldowns, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD: Id<GuildMarker> = Id::new(1);
+    const OTHER_GUILD: Id<GuildMarker> = Id::new(2);
+    const ALICE: Id<UserMarker> = Id::new(10);
+    const BOB: Id<UserMarker> = Id::new(11);
+
+    fn user(n: u64) -> Id<UserMarker> {
+        Id::new(1000 + n)
+    }
+
+    fn len(store: &CooldownStore, guild: Id<GuildMarker>) -> usize {
+        store.guilds.read().unwrap()[&guild]
+            .lock()
+            .unwrap()
+            .expiries
+            .len()
+    }
+
+    #[test]
+    fn cooldown_blocks_until_expiry() {
+        let store = CooldownStore::new();
+        assert!(store.try_start(GUILD, ALICE, 100, 60).unwrap());
+        assert!(!store.try_start(GUILD, ALICE, 159, 60).unwrap());
+        // other members and guilds have their own cooldowns
+        assert!(store.try_start(GUILD, BOB, 159, 60).unwrap());
+        assert!(store.try_start(OTHER_GUILD, ALICE, 159, 60).unwrap());
+        assert!(store.try_start(GUILD, ALICE, 160, 60).unwrap());
+        assert!(!store.try_start(GUILD, ALICE, 219, 60).unwrap());
+    }
+
+    #[test]
+    fn expired_cooldowns_are_swept() {
+        let store = CooldownStore::new();
+        for n in 0..1000 {
+            assert!(store.try_start(GUILD, user(n), 0, 60).unwrap());
+        }
+        assert_eq!(len(&store, GUILD), 1000);
+        // the first thousand have all expired, so the next sweep throws them out
+        for n in 1000..1100 {
+            assert!(store.try_start(GUILD, user(n), 100, 60).unwrap());
+            assert!(len(&store, GUILD) <= 1024);
+        }
+        assert_eq!(len(&store, GUILD), 100);
+        assert!(store.try_start(GUILD, user(0), 100, 60).unwrap());
+        assert!(!store.try_start(GUILD, user(1050), 159, 60).unwrap());
+    }
+
+    #[test]
+    fn restart_keeps_running_cooldowns() {
+        let store = CooldownStore::new();
+        store.try_start(GUILD, ALICE, 100, 60).unwrap();
+        store.try_start(GUILD, BOB, 50, 60).unwrap();
+        store.try_start(OTHER_GUILD, ALICE, 100, 10).unwrap();
+
+        let mut snapshot = store.snapshot(120).unwrap();
+        snapshot.sort_by_key(|c| (c.guild, c.user));
+        assert_eq!(
+            snapshot,
+            [Cooldown {
+                guild: GUILD,
+                user: ALICE,
+                expires: 160
+            }]
+        );
+
+        let restarted = CooldownStore::new();
+        restarted.restore(snapshot.clone(), 130).unwrap();
+        assert!(!restarted.try_start(GUILD, ALICE, 130, 60).unwrap());
+        assert!(restarted.try_start(GUILD, BOB, 130, 60).unwrap());
+        assert!(restarted.try_start(GUILD, ALICE, 160, 60).unwrap());
+
+        // snapshots which are too old don't block anyone
+        let late = CooldownStore::new();
+        late.restore(snapshot, 160).unwrap();
+        assert!(late.try_start(GUILD, ALICE, 160, 60).unwrap());
+    }
+}
@@ -1,33 +1,46 @@
 use std::{
     collections::HashMap,
     ops::Deref,
-    sync::{Arc, RwLock},
+    sync::{Arc, Mutex, RwLock},
 };
 
-use ahash::AHashMap;
-use sqlx::{query, query_as, PgPool};
+use simpleinterpolation::Filters;
+use sqlx::{query, PgPool};
+use tokio::sync::OnceCell;
 use tokio_util::task::TaskTracker;
 use twilight_cache_inmemory::{InMemoryCache, ResourceType};
 use twilight_gateway::EventTypeFlags;
 use twilight_model::{
-    gateway::{event::Event, Intents},
+    gateway::{event::Event, payload::incoming::GuildCreate, Intents},
     id::{
-        marker::{ApplicationMarker, GuildMarker, RoleMarker, UserMarker},
+        marker::{ApplicationMarker, GuildMarker, RoleMarker},
         Id,
     },
 };
-use xpd_common::{db_to_id, id_to_db, GuildConfig, RawGuildConfig, RequiredEvents, RoleReward};
+use xpd_common::{
+    db_to_id, id_to_db, query_guild_config, stored_template, CacheInvalidator, CacheKind,
+    GuildConfig, RawGuildConfig, RequiredEvents, RoleReward,
+};
+
+use crate::{
+    announce::LevelUpMessages,
+    cooldown::CooldownStore,
+    multipliers::{ChannelRules, RoleRules},
+    voice::GuildVoice,
+};
 
+mod announce;
+mod award;
+mod cooldown;
 mod message;
+mod multipliers;
+mod notify;
+mod rank_rewards;
+mod voice;
 
 #[macro_use]
 extern crate tracing;
 
-// TODO: Maybe we can improve the locking on this. Have one task per guild or something.
-// Go philosophy is good, we want to share memory by communicating.
-// We use i64 here, because it makes some database stuff easier and
-// it's impossible for a discord snowflake timestamp to exceed i64
-type SentMessages = AHashMap<(Id<GuildMarker>, Id<UserMarker>), i64>;
 type LockingMap<K, V> = RwLock<HashMap<K, V>>;
 
 #[derive(Clone)]
@@ -64,7 +77,7 @@ impl RequiredEvents for XpdListener {
 
 pub struct XpdListenerInner {
     db: PgPool,
-    messages: RwLock<SentMessages>,
+    cooldowns: CooldownStore,
     http: Arc<twilight_http::Client>,
     // https://github.com/twilight-rs/twilight/tree/main/examples/cache-optimization/models
     // TODO: Use custom cache models
@@ -73,6 +86,13 @@ pub struct XpdListenerInner {
     task_tracker: TaskTracker,
     configs: LockingMap<Id<GuildMarker>, Arc<GuildConfig>>,
     rewards: LockingMap<Id<GuildMarker>, Arc<Vec<RoleReward>>>,
+    prestige_rewards: LockingMap<Id<GuildMarker>, Arc<Vec<RoleReward>>>,
+    channel_rules: LockingMap<Id<GuildMarker>, Arc<ChannelRules>>,
+    role_rules: LockingMap<Id<GuildMarker>, Arc<RoleRules>>,
+    level_up_messages: LockingMap<Id<GuildMarker>, Arc<LevelUpMessages>>,
+    voice: Mutex<HashMap<Id<GuildMarker>, GuildVoice>>,
+    backfills: Mutex<HashMap<Id<GuildMarker>, Arc<OnceCell<()>>>>,
+    filters: Filters,
     current_application_id: Id<ApplicationMarker>,
 }
 
@@ -83,9 +103,16 @@ impl XpdListenerInner {
         task_tracker: TaskTracker,
         current_application_id: Id<ApplicationMarker>,
     ) -> Self {
-        let messages = RwLock::new(SentMessages::new());
+        let cooldowns = CooldownStore::new();
         let configs = RwLock::new(HashMap::new());
         let rewards = RwLock::new(HashMap::new());
+        let prestige_rewards = RwLock::new(HashMap::new());
+        let channel_rules = RwLock::new(HashMap::new());
+        let role_rules = RwLock::new(HashMap::new());
+        let level_up_messages = RwLock::new(HashMap::new());
+        let voice = Mutex::new(HashMap::new());
+        let backfills = Mutex::new(HashMap::new());
+        let filters = announce::level_up_filters();
         let resource_types = ResourceType::USER_CURRENT
             | ResourceType::ROLE
             | ResourceType::GUILD
@@ -98,10 +125,17 @@ impl XpdListenerInner {
 
         Self {
             db,
-            messages,
+            cooldowns,
             http,
             configs,
             rewards,
+            prestige_rewards,
+            channel_rules,
+            role_rules,
+            level_up_messages,
+            voice,
+            backfills,
+            filters,
             cache,
             task_tracker,
             current_application_id,
@@ -112,8 +146,44 @@ impl XpdListenerInner {
         self.cache.update(uc);
     }
 
-    pub fn update_config(&self, guild: Id<GuildMarker>, config: GuildConfig) -> Result<(), Error> {
-        self.configs.write()?.insert(guild, Arc::new(config));
+    /// Load a guild into the cache over HTTP if it isn't there yet, for when the gateway won't
+    /// send it to us, like after resuming a session from a previous process. Call this before
+    /// [`Self::update_cache`], so the guild's events wait for it instead of being overwritten by it.
+    /// Events for a guild share a single load, and if it fails, the guild's next event tries again.
+    /// Voice states can't be fetched this way, so those come from [`Self::restore_voice`] instead.
+    pub async fn ensure_cached(&self, guild_id: Id<GuildMarker>) -> Result<(), Error> {
+        if self.cache.guild(guild_id).is_some() {
+            return Ok(());
+        }
+        let backfill = self.backfills.lock()?.entry(guild_id).or_default().clone();
+        let loaded = backfill
+            .get_or_try_init(|| async {
+                self.backfill_guild(guild_id).await?;
+                debug!(?guild_id, "Loaded resumed guild into cache");
+                Ok::<_, Error>(())
+            })
+            .await;
+        match loaded {
+            // the cache answers for this guild from now on
+            Ok(()) => {
+                self.backfills.lock()?.remove(&guild_id);
+            }
+            Err(source) => warn!(?guild_id, ?source, "Could not load guild into cache"),
+        }
+        Ok(())
+    }
+
+    async fn backfill_guild(&self, guild_id: Id<GuildMarker>) -> Result<(), Error> {
+        let mut guild = self.http.guild(guild_id).await?.model().await?;
+        guild.channels = self.http.guild_channels(guild_id).await?.models().await?;
+        let me = self
+            .http
+            .guild_member(guild_id, self.current_application_id.cast())
+            .await?
+            .model()
+            .await?;
+        guild.members = vec![me];
+        self.cache.update(&GuildCreate(guild));
         Ok(())
     }
 
@@ -124,11 +194,9 @@ impl XpdListenerInner {
         if let Some(guild_config) = self.configs.read()?.get(&guild) {
             return Ok(guild_config.clone());
         }
-        let config = query_as!(
-            RawGuildConfig,
-            "SELECT one_at_a_time, level_up_message, level_up_channel, ping_on_level_up,\
-             max_xp_per_message, min_xp_per_message, message_cooldown \
-             FROM guild_configs WHERE id = $1",
+        let config = query_guild_config!(
+            "SELECT ",
+            " FROM guild_configs WHERE id = $1",
             id_to_db(guild)
         )
         .fetch_optional(&self.db)
@@ -140,10 +208,21 @@ impl XpdListenerInner {
         Ok(config)
     }
 
+    /// Forget a guild's config, so it's loaded again when it's next needed.
+    pub fn invalidate_config(&self, guild: Id<GuildMarker>) -> Result<(), Error> {
+        self.configs.write()?.remove(&guild);
+        Ok(())
+    }
+
     pub async fn invalidate_rewards(&self, guild: Id<GuildMarker>) -> Result<(), Error> {
         let mut new_rewards = self.get_guild_rewards_uncached(guild).await?;
         new_rewards.sort_by(xpd_common::sort_rewards);
         self.rewards.write()?.insert(guild, Arc::new(new_rewards));
+        let mut new_prestige_rewards = self.get_prestige_rewards_uncached(guild).await?;
+        new_prestige_rewards.sort_by(xpd_common::sort_rewards);
+        self.prestige_rewards
+            .write()?
+            .insert(guild, Arc::new(new_prestige_rewards));
         Ok(())
     }
 
@@ -167,7 +246,7 @@ impl XpdListenerInner {
         guild_id: Id<GuildMarker>,
     ) -> Result<Vec<RoleReward>, Error> {
         let rewards: Vec<RoleReward> = query!(
-            "SELECT id, requirement FROM role_rewards WHERE guild = $1",
+            "SELECT id, requirement, remove FROM role_rewards WHERE guild = $1",
             id_to_db(guild_id),
         )
         .fetch_all(&self.db)
@@ -176,15 +255,197 @@ impl XpdListenerInner {
         .map(|row| RoleReward {
             id: db_to_id(row.id),
             requirement: row.requirement,
+            remove: row.remove,
+        })
+        .collect();
+        Ok(rewards)
+    }
+
+    /// Reward roles for prestige tiers, where each reward's `requirement` is the tier
+    pub async fn get_prestige_rewards(
+        &self,
+        guild_id: Id<GuildMarker>,
+    ) -> Result<Arc<Vec<RoleReward>>, Error> {
+        if let Some(rewards) = self.prestige_rewards.read()?.get(&guild_id) {
+            return Ok(rewards.clone());
+        }
+        let mut rewards = self.get_prestige_rewards_uncached(guild_id).await?;
+        rewards.sort_by(xpd_common::sort_rewards);
+
+        let new_copy = Arc::new(rewards);
+        self.prestige_rewards
+            .write()?
+            .insert(guild_id, new_copy.clone());
+        Ok(new_copy)
+    }
+
+    async fn get_prestige_rewards_uncached(
+        &self,
+        guild_id: Id<GuildMarker>,
+    ) -> Result<Vec<RoleReward>, Error> {
+        let rewards: Vec<RoleReward> = query!(
+            "SELECT id, tier FROM prestige_rewards WHERE guild = $1",
+            id_to_db(guild_id),
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|row| RoleReward {
+            id: db_to_id(row.id),
+            requirement: row.tier,
+            remove: false,
         })
         .collect();
         Ok(rewards)
     }
+
+    pub async fn invalidate_channel_rules(&self, guild: Id<GuildMarker>) -> Result<(), Error> {
+        let new_rules = self.get_channel_rules_uncached(guild).await?;
+        self.channel_rules
+            .write()?
+            .insert(guild, Arc::new(new_rules));
+        Ok(())
+    }
+
+    pub async fn get_channel_rules(
+        &self,
+        guild_id: Id<GuildMarker>,
+    ) -> Result<Arc<ChannelRules>, Error> {
+        if let Some(rules) = self.channel_rules.read()?.get(&guild_id) {
+            return Ok(rules.clone());
+        }
+        let rules = Arc::new(self.get_channel_rules_uncached(guild_id).await?);
+        self.channel_rules.write()?.insert(guild_id, rules.clone());
+        Ok(rules)
+    }
+
+    async fn get_channel_rules_uncached(
+        &self,
+        guild_id: Id<GuildMarker>,
+    ) -> Result<ChannelRules, Error> {
+        let rules = query!(
+            "SELECT id, multiplier FROM channel_xp_rules WHERE guild = $1",
+            id_to_db(guild_id),
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|row| (db_to_id(row.id), row.multiplier))
+        .collect();
+        Ok(rules)
+    }
+
+    pub async fn invalidate_role_rules(&self, guild: Id<GuildMarker>) -> Result<(), Error> {
+        let new_rules = self.get_role_rules_uncached(guild).await?;
+        self.role_rules.write()?.insert(guild, Arc::new(new_rules));
+        Ok(())
+    }
+
+    pub async fn get_role_rules(&self, guild_id: Id<GuildMarker>) -> Result<Arc<RoleRules>, Error> {
+        if let Some(rules) = self.role_rules.read()?.get(&guild_id) {
+            return Ok(rules.clone());
+        }
+        let rules = Arc::new(self.get_role_rules_uncached(guild_id).await?);
+        self.role_rules.write()?.insert(guild_id, rules.clone());
+        Ok(rules)
+    }
+
+    async fn get_role_rules_uncached(&self, guild_id: Id<GuildMarker>) -> Result<RoleRules, Error> {
+        let rules = query!(
+            "SELECT id, multiplier FROM role_xp_multipliers WHERE guild = $1",
+            id_to_db(guild_id),
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|row| (db_to_id(row.id), row.multiplier))
+        .collect();
+        Ok(rules)
+    }
+
+    pub async fn invalidate_level_up_messages(&self, guild: Id<GuildMarker>) -> Result<(), Error> {
+        let new_messages = self.get_level_up_messages_uncached(guild).await?;
+        self.level_up_messages
+            .write()?
+            .insert(guild, Arc::new(new_messages));
+        Ok(())
+    }
+
+    pub async fn get_level_up_messages(
+        &self,
+        guild_id: Id<GuildMarker>,
+    ) -> Result<Arc<LevelUpMessages>, Error> {
+        if let Some(messages) = self.level_up_messages.read()?.get(&guild_id) {
+            return Ok(messages.clone());
+        }
+        let messages = Arc::new(self.get_level_up_messages_uncached(guild_id).await?);
+        self.level_up_messages
+            .write()?
+            .insert(guild_id, messages.clone());
+        Ok(messages)
+    }
+
+    async fn get_level_up_messages_uncached(
+        &self,
+        guild_id: Id<GuildMarker>,
+    ) -> Result<LevelUpMessages, Error> {
+        let rows = query!(
+            "SELECT level, message FROM level_up_messages WHERE guild = $1 ORDER BY id",
+            id_to_db(guild_id),
+        )
+        .fetch_all(&self.db)
+        .await?;
+        let mut messages = LevelUpMessages::default();
+        for row in rows {
+            let Some(template) = stored_template("level_up_messages.message", &row.message) else {
+                continue;
+            };
+            match row.level {
+                Some(level) => {
+                    messages.overrides.insert(level, template);
+                }
+                None => messages.variants.push(template),
+            }
+        }
+        Ok(messages)
+    }
+}
+
+impl CacheInvalidator for XpdListenerInner {
+    async fn invalidate(&self, kind: CacheKind, guild: Id<GuildMarker>) {
+        let result = match kind {
+            CacheKind::Config => self.invalidate_config(guild),
+            CacheKind::Rewards => self.invalidate_rewards(guild).await,
+            CacheKind::ChannelRules => self.invalidate_channel_rules(guild).await,
+            CacheKind::RoleRules => self.invalidate_role_rules(guild).await,
+            CacheKind::LevelUpMessages => self.invalidate_level_up_messages(guild).await,
+        };
+        if let Err(source) = result {
+            error!(
+                ?guild,
+                ?kind,
+                ?source,
+                "Unable to invalidate cache for guild"
+            );
+        }
+    }
+
+    async fn update_config(&self, guild: Id<GuildMarker>, config: GuildConfig) {
+        match self.configs.write() {
+            Ok(mut configs) => {
+                configs.insert(guild, Arc::new(config));
+            }
+            Err(_) => error!(?guild, "Unable to update config for guild, lock poisoned"),
+        }
+    }
 }
 
 impl RequiredEvents for XpdListenerInner {
     fn required_intents() -> Intents {
-        Intents::GUILDS | Intents::GUILD_MESSAGES | Intents::MESSAGE_CONTENT
+        Intents::GUILDS
+            | Intents::GUILD_MESSAGES
+            | Intents::MESSAGE_CONTENT
+            | Intents::GUILD_VOICE_STATES
     }
 
     fn required_events() -> EventTypeFlags {
@@ -202,6 +463,7 @@ impl RequiredEvents for XpdListenerInner {
             | EventTypeFlags::THREAD_LIST_SYNC
             | EventTypeFlags::THREAD_DELETE
             | EventTypeFlags::MESSAGE_CREATE
+            | EventTypeFlags::VOICE_STATE_UPDATE
     }
 }
 
@@ -210,9 +472,15 @@ pub enum Error {
     #[error("SQL error")]
     Sqlx(#[from] sqlx::Error),
     #[error("Discord error")]
-    Twilight(#[from] twilight_http::Error),
+    Twilight(Box<twilight_http::Error>),
+    #[error("Discord sent an invalid response")]
+    DeserializeBody(#[from] twilight_http::response::DeserializeBodyError),
     #[error("simpleinterpolation failed")]
     CouldNotInterpolate(#[from] simpleinterpolation::Error),
+    #[error("Invalid guild config")]
+    InvalidGuildConfig(#[from] xpd_common::GuildConfigError),
+    #[error("Invalid embed image")]
+    InvalidImageSource(#[from] twilight_util::builder::embed::image_source::ImageSourceUrlError),
     #[error("Unknown permissions for role")]
     UnknownPermissionsForRole(#[from] twilight_cache_inmemory::permission::RootError),
     #[error("Unknown permissions for role")]
@@ -231,6 +499,13 @@ pub enum Error {
     UnknownPositionForOwnHighestRole,
 }
 
+impl From<twilight_http::Error> for Error {
+    fn from(source: twilight_http::Error) -> Self {
+        // twilight's errors are big enough to bloat every `Result` in the crate
+        Self::Twilight(Box::new(source))
+    }
+}
+
 impl<T> From<std::sync::PoisonError<T>> for Error {
     fn from(_: std::sync::PoisonError<T>) -> Self {
         Self::LockPoisoned
@@ -1,5 +1,4 @@
-use std::collections::HashMap;
-
+use simpleinterpolation::Interpolation;
 use sqlx::query;
 use twilight_cache_inmemory::CacheableRole;
 use twilight_model::{
@@ -7,13 +6,18 @@ use twilight_model::{
     gateway::payload::incoming::MessageCreate,
     guild::Permissions,
     id::{
-        marker::{ChannelMarker, GuildMarker, RoleMarker},
+        marker::{ChannelMarker, GuildMarker, RoleMarker, UserMarker},
         Id,
     },
+    user::User,
+};
+use twilight_util::builder::embed::{EmbedBuilder, ImageSource};
+use xpd_common::{
+    id_to_db, snowflake_to_timestamp, GuildConfig, RewardRoleChanges, DEFAULT_LEVEL_UP_EMBED_COLOR,
+    DEFAULT_MESSAGE_COOLDOWN,
 };
-use xpd_common::{id_to_db, snowflake_to_timestamp, RoleReward, DEFAULT_MESSAGE_COOLDOWN};
 
-use crate::{Error, XpdListenerInner};
+use crate::{announce::LevelUpVariables, Error, XpdListenerInner};
 
 impl XpdListenerInner {
     pub async fn save(&self, msg: MessageCreate) -> Result<(), Error> {
@@ -33,34 +37,46 @@ impl XpdListenerInner {
             return Ok(());
         }
 
-        let user_cooldown_key = (guild_id, msg.author.id);
+        let Some(member) = &msg.member else {
+            return Err(Error::NoMember);
+        };
+
+        let multiplier = self
+            .message_multiplier(guild_id, msg.channel_id, &member.roles)
+            .await?;
+        if multiplier <= 0.0 {
+            return Ok(());
+        }
+
         let this_message_sts = snowflake_to_timestamp(msg.id);
 
         let guild_config = self.get_guild_config(guild_id).await?;
 
-        // if the last message timestamp plus the cooldown period is larger than the current sent at epoch,
-        // we want to return immediately because the "expiry time" is still in the future
+        // starting the cooldown before awarding XP means two messages sent at once
+        // can't both earn it
         let cooldown: i64 = guild_config
             .cooldown
             .unwrap_or(DEFAULT_MESSAGE_COOLDOWN)
             .into();
-        if self
-            .messages
-            .read()?
-            .get(&user_cooldown_key)
-            .is_some_and(|last_message_sts| last_message_sts + cooldown > this_message_sts)
+        if !self
+            .cooldowns
+            .try_start(guild_id, msg.author.id, this_message_sts, cooldown)?
         {
             return Ok(());
         }
 
-        let guild_config = self.get_guild_config(guild_id).await?;
-
-        let xp_added = ((msg.content.chars().count() as f64) / 10.0).sqrt().ceil() as i64;
+        let multiplier = multiplier
+            * self
+                .prestige_multiplier(guild_id, msg.author.id, &guild_config)
+                .await?;
+        let base_xp =
+            crate::award::message_xp(&guild_config, &msg.content, &mut rand::thread_rng());
+        let xp_added = (base_xp as f64 * f64::from(multiplier)).round() as i64;
         let xp_record = query!(
             "INSERT INTO levels (id, xp, guild) VALUES ($1, $2, $3) \
                 ON CONFLICT (id, guild) \
                 DO UPDATE SET xp=levels.xp+excluded.xp \
-                RETURNING xp",
+                RETURNING xp, prestige",
             id_to_db(msg.author.id),
             xp_added,
             id_to_db(guild_id)
@@ -71,86 +87,76 @@ impl XpdListenerInner {
         let xp = u64::try_from(xp_record.xp).unwrap_or(0);
         let old_xp = u64::try_from(xp_record.xp - xp_added).unwrap_or(0);
 
-        self.messages
-            .write()?
-            .insert(user_cooldown_key, this_message_sts);
-
-        let level_info = mee6::LevelInfo::new(xp);
-        let old_level_info = mee6::LevelInfo::new(old_xp);
-
-        let rewards = self.get_guild_rewards(guild_id).await?;
-
-        trace!(
-            ?rewards,
-            guild_id = guild_id.get(),
-            "Got & sorted rewards for guild"
-        );
+        let curve = guild_config.level_curve();
+        let level_info = mee6::LevelInfo::new(&*curve, xp);
+        let old_level_info = mee6::LevelInfo::new(&*curve, old_xp);
 
         let user_level: i64 = level_info.level().try_into().unwrap_or(-1);
         let old_user_level: i64 = old_level_info.level().try_into().unwrap_or(-1);
 
-        let mut reward_idx = None;
-        for (idx, data) in rewards.iter().enumerate() {
-            if data.requirement > user_level {
-                break;
-            }
-            reward_idx = Some(idx);
-        }
-
-        let Some(member) = &msg.member else {
-            return Err(Error::NoMember);
-        };
-
         debug!(user = ?msg.author.id, channel = ?msg.channel_id, old_xp, new_xp = xp, user_level, old_user_level, config = ?guild_config, "Preparing to update user");
 
-        if let Some(reward_idx) = reward_idx {
-            // remove all role IDs which are in our rewards list
-            let base_roles: Vec<Id<RoleMarker>> = member
-                .roles
-                .iter()
-                .filter(|role_id| !contains(&rewards, **role_id))
-                .copied()
-                .collect();
-
-            trace!(base_roles = ?base_roles, "got roles we cannot change");
-
-            let new_roles = if guild_config.one_at_a_time.is_some_and(|v| v) {
-                vec![rewards[reward_idx].id]
-            } else {
-                rewards[..=reward_idx].iter().map(|v| v.id).collect()
-            };
-
-            trace!(new_roles = ?new_roles, "got roles we are trying to control");
-
-            let mut complete_role_set: Vec<Id<RoleMarker>> =
-                Vec::with_capacity(new_roles.len() + base_roles.len());
-
-            complete_role_set.extend(&base_roles);
-            complete_role_set.extend(&new_roles);
-
-            // make sure we don't make useless requests to the API
-            let can_add_role = self
-                .can_add_roles(guild_id, new_roles.as_slice())?
-                .can_add_role();
-            if member.roles != new_roles && can_add_role {
-                debug!(user = ?msg.author.id, old = ?member.roles, new = ?new_roles, "Updating roles for user");
-                self.http
-                    .update_guild_member(guild_id, msg.author.id)
-                    .roles(&complete_role_set)
-                    .await?;
-            }
-        };
+        let reward_role = self
+            .sync_reward_roles(
+                guild_id,
+                msg.author.id,
+                &member.roles,
+                user_level,
+                xp_record.prestige,
+                &guild_config,
+            )
+            .await?;
 
         if user_level > old_user_level {
-            if let Some(template) = guild_config.level_up_message.as_ref() {
+            let level_up_messages = self.get_level_up_messages(guild_id).await?;
+            let template = level_up_messages.pick(
+                user_level,
+                guild_config.level_up_message.as_ref(),
+                &mut rand::thread_rng(),
+            );
+            if let Some(template) = template {
                 let target_channel = guild_config.level_up_channel.unwrap_or(msg.channel_id);
                 debug!(user = ?msg.author.id, channel = ?msg.channel_id, ?target_channel, old = old_user_level, new = user_level, "Congratulating user");
                 if self.can_create_message(target_channel)? {
-                    let map = HashMap::from([
-                        ("user_mention".to_string(), format!("<@{}>", msg.author.id)),
-                        ("level".to_string(), user_level.to_string()),
-                    ]);
-                    let message = template.render(&map);
+                    let title = guild_config
+                        .level_up_embed_title
+                        .as_ref()
+                        .filter(|_| guild_config.level_up_embed.is_some_and(|v| v));
+                    // ranking needs another query, so only do it when someone wants to see it
+                    let rank = if template
+                        .variables_used()
+                        .chain(title.into_iter().flat_map(Interpolation::variables_used))
+                        .any(|name| name == "rank")
+                    {
+                        Some(
+                            self.rank(guild_id, xp_record.prestige, xp_record.xp)
+                                .await?,
+                        )
+                    } else {
+                        None
+                    };
+                    let variables = LevelUpVariables {
+                        user_id: msg.author.id,
+                        user_name: &msg.author.name,
+                        display_name: member
+                            .nick
+                            .as_deref()
+                            .or(msg.author.global_name.as_deref())
+                            .unwrap_or(&msg.author.name),
+                        level: user_level,
+                        old_level: old_user_level,
+                        xp,
+                        next_level_xp: level_info.next_level_xp(),
+                        prestige: xp_record.prestige,
+                        rank,
+                        guild_name: self
+                            .cache
+                            .guild(guild_id)
+                            .map(|guild| guild.name().to_string()),
+                        channel_id: msg.channel_id,
+                        reward_role,
+                    };
+                    let message = variables.render(template, &self.filters);
 
                     let allowed_mentions = if let Some(false) = guild_config.ping_on_level_up {
                         AllowedMentions::default()
@@ -162,15 +168,35 @@ impl XpdListenerInner {
                         }
                     };
 
-                    let mut congratulatory_msg = self.http.create_message(target_channel);
+                    let mut congratulatory_msg = self
+                        .http
+                        .create_message(target_channel)
+                        .allowed_mentions(Some(&allowed_mentions));
                     if target_channel == msg.channel_id {
                         // only reply to a message if it's in the same channel
                         congratulatory_msg = congratulatory_msg.reply(msg.id);
                     }
-                    congratulatory_msg
-                        .allowed_mentions(Some(&allowed_mentions))
-                        .content(&message)
-                        .await?;
+                    if guild_config.level_up_embed.is_some_and(|v| v) {
+                        let mut embed = EmbedBuilder::new().description(message).color(
+                            guild_config
+                                .level_up_embed_color
+                                .unwrap_or(DEFAULT_LEVEL_UP_EMBED_COLOR),
+                        );
+                        if let Some(title) = title {
+                            embed = embed.title(variables.render(title, &self.filters));
+                        }
+                        if guild_config.level_up_embed_thumbnail.is_some_and(|v| v) {
+                            embed = embed.thumbnail(ImageSource::url(avatar_url(&msg.author))?);
+                        }
+                        // mentions in embeds never ping, so ping in the message itself
+                        let mention = format!("<@{}>", msg.author.id);
+                        congratulatory_msg
+                            .content(&mention)
+                            .embeds(&[embed.build()])
+                            .await?;
+                    } else {
+                        congratulatory_msg.content(&message).await?;
+                    }
                 } else {
                     warn!(channel = ?msg.channel_id, "Could not congratulate user")
                 }
@@ -179,6 +205,76 @@ impl XpdListenerInner {
         Ok(())
     }
 
+    /// Where a member with `xp` experience at prestige tier `prestige` places on the
+    /// guild's leaderboard, starting from 1.
+    async fn rank(&self, guild_id: Id<GuildMarker>, prestige: i64, xp: i64) -> Result<i64, Error> {
+        let count = query!(
+            "SELECT COUNT(*) as count FROM levels WHERE (prestige, xp) > ($1, $2) AND guild = $3",
+            prestige,
+            xp,
+            id_to_db(guild_id)
+        )
+        .fetch_one(&self.db)
+        .await?
+        .count
+        .unwrap_or(0);
+        Ok(count + 1)
+    }
+
+    /// Add and remove reward roles so that they match the member's level and prestige tier,
+    /// returning the newest level reward role they were given, if any.
+    pub(crate) async fn sync_reward_roles(
+        &self,
+        guild_id: Id<GuildMarker>,
+        user_id: Id<UserMarker>,
+        current_roles: &[Id<RoleMarker>],
+        level: i64,
+        prestige: i64,
+        guild_config: &GuildConfig,
+    ) -> Result<Option<Id<RoleMarker>>, Error> {
+        let rewards = self.get_guild_rewards(guild_id).await?;
+        trace!(
+            ?rewards,
+            guild_id = guild_id.get(),
+            "Got & sorted rewards for guild"
+        );
+
+        let changes = RewardRoleChanges::new(
+            current_roles,
+            &rewards,
+            level,
+            guild_config.one_at_a_time.is_some_and(|v| v),
+        );
+        let changes = if guild_config.prestige_level().is_some() {
+            let prestige_rewards = self.get_prestige_rewards(guild_id).await?;
+            changes.merge(RewardRoleChanges::new(
+                current_roles,
+                &prestige_rewards,
+                prestige,
+                false,
+            ))
+        } else {
+            changes
+        };
+        // make sure we don't make useless requests to the API
+        if changes.is_empty() {
+            return Ok(None);
+        }
+
+        let changed: Vec<Id<RoleMarker>> = changes.changed().collect();
+        if !self.can_add_roles(guild_id, &changed)?.can_add_role() {
+            return Ok(None);
+        }
+
+        let complete_role_set = changes.apply(current_roles);
+        debug!(user = ?user_id, old = ?current_roles, add = ?changes.add, remove = ?changes.remove, "Updating roles for user");
+        self.http
+            .update_guild_member(guild_id, user_id)
+            .roles(&complete_role_set)
+            .await?;
+        Ok(changes.newest_added(&rewards))
+    }
+
     fn can_add_roles(
         &self,
         guild_id: Id<GuildMarker>,
@@ -256,7 +352,14 @@ impl CanAddRole {
     }
 }
 
-// any of the items in list are equal to item
-fn contains(list: &[RoleReward], item: Id<RoleMarker>) -> bool {
-    list.iter().any(|v| v.id == item)
+fn avatar_url(user: &User) -> String {
+    user.avatar.map_or_else(
+        || {
+            format!(
+                "https://cdn.discordapp.com/embed/avatars/{}.png",
+                (user.id.get() >> 22) % 6
+            )
+        },
+        |hash| format!("https://cdn.discordapp.com/avatars/{}/{hash}.png", user.id),
+    )
 }
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use sqlx::query;
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker, RoleMarker, UserMarker},
+    Id,
+};
+use xpd_common::{id_to_db, GuildConfig};
+
+use crate::{Error, XpdListenerInner};
+
+/// XP multipliers for channels, categories and thread parents, keyed by channel ID
+pub type ChannelRules = HashMap<Id<ChannelMarker>, f32>;
+/// XP multipliers for roles, keyed by role ID
+pub type RoleRules = HashMap<Id<RoleMarker>, f32>;
+
+impl XpdListenerInner {
+    /// Get the total XP multiplier for a message, which is the channel multiplier
+    /// times the role multiplier. A result of `0.0` means the message earns no XP.
+    pub(crate) async fn message_multiplier(
+        &self,
+        guild_id: Id<GuildMarker>,
+        channel_id: Id<ChannelMarker>,
+        roles: &[Id<RoleMarker>],
+    ) -> Result<f32, Error> {
+        let channel = self.channel_multiplier(guild_id, channel_id).await?;
+        if channel <= 0.0 {
+            return Ok(0.0);
+        }
+        let role_rules = self.get_role_rules(guild_id).await?;
+        Ok(channel * stack_role_multipliers(&role_rules, roles))
+    }
+
+    /// Get the XP multiplier a member earns from their prestige tier. This only
+    /// needs a query when the guild actually rewards prestige with extra XP.
+    pub(crate) async fn prestige_multiplier(
+        &self,
+        guild_id: Id<GuildMarker>,
+        user_id: Id<UserMarker>,
+        guild_config: &GuildConfig,
+    ) -> Result<f32, Error> {
+        if guild_config.prestige_xp_multiplier.is_none() || guild_config.prestige_level().is_none()
+        {
+            return Ok(1.0);
+        }
+        let tier = query!(
+            "SELECT prestige FROM levels WHERE id = $1 AND guild = $2",
+            id_to_db(user_id),
+            id_to_db(guild_id)
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .map_or(0, |row| row.prestige);
+        Ok(guild_config.prestige_multiplier(tier))
+    }
+
+    /// Find the XP multiplier for a channel. Rules set on the channel itself win,
+    /// then its parent (the channel a thread is in, or a channel's category),
+    /// then that channel's parent. Channels without any rule get `1.0`.
+    async fn channel_multiplier(
+        &self,
+        guild_id: Id<GuildMarker>,
+        channel_id: Id<ChannelMarker>,
+    ) -> Result<f32, Error> {
+        let rules = self.get_channel_rules(guild_id).await?;
+        if rules.is_empty() {
+            return Ok(1.0);
+        }
+        let mut current = Some(channel_id);
+        // a thread's parent's parent is a category, and nothing can go deeper than that
+        for _ in 0..3 {
+            let Some(channel_id) = current else {
+                break;
+            };
+            if let Some(multiplier) = rules.get(&channel_id) {
+                trace!(?channel_id, multiplier, "found channel rule");
+                return Ok(*multiplier);
+            }
+            current = self
+                .cache
+                .channel(channel_id)
+                .and_then(|channel| channel.parent_id);
+        }
+        Ok(1.0)
+    }
+}
+
+/// Combine the multipliers of every role a member has.
+///
+/// Role multipliers do not stack: if any of the member's roles blocks XP (has a
+/// multiplier of `0`), the result is `0.0`. Otherwise, the highest multiplier
+/// wins. Members without any multiplied roles get `1.0`.
+pub fn stack_role_multipliers(rules: &RoleRules, roles: &[Id<RoleMarker>]) -> f32 {
+    let mut highest: Option<f32> = None;
+    for multiplier in roles.iter().filter_map(|role| rules.get(role)) {
+        if *multiplier <= 0.0 {
+            return 0.0;
+        }
+        highest = Some(highest.map_or(*multiplier, |v| v.max(*multiplier)));
+    }
+    highest.unwrap_or(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOSTER: Id<RoleMarker> = Id::new(1);
+    const PATRON: Id<RoleMarker> = Id::new(2);
+    const MUTED: Id<RoleMarker> = Id::new(3);
+    const PENALTY: Id<RoleMarker> = Id::new(4);
+    const OTHER: Id<RoleMarker> = Id::new(5);
+
+    fn rules() -> RoleRules {
+        RoleRules::from([(BOOSTER, 1.5), (PATRON, 2.0), (MUTED, 0.0), (PENALTY, 0.5)])
+    }
+
+    #[test]
+    fn no_rules() {
+        assert!((stack_role_multipliers(&rules(), &[OTHER]) - 1.0).abs() < f32::EPSILON);
+        assert!((stack_role_multipliers(&rules(), &[]) - 1.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn highest_wins() {
+        let multiplier = stack_role_multipliers(&rules(), &[BOOSTER, OTHER, PATRON]);
+        assert!((multiplier - 2.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn penalty_alone_applies() {
+        let multiplier = stack_role_multipliers(&rules(), &[PENALTY, OTHER]);
+        assert!((multiplier - 0.5).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn blocked_role_beats_everything() {
+        let multiplier = stack_role_multipliers(&rules(), &[PATRON, MUTED, BOOSTER]);
+        assert!(multiplier.abs() < f32::EPSILON);
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use sqlx::postgres::PgListener;
+use tokio_util::sync::CancellationToken;
+use xpd_common::{CacheInvalidation, CacheInvalidator, CACHE_INVALIDATION_CHANNEL};
+
+use crate::{Error, XpdListenerInner};
+
+/// How long to wait before trying to listen again after the database refuses us
+const RELISTEN_DELAY: Duration = Duration::from_secs(5);
+
+impl XpdListenerInner {
+    /// Invalidate caches whenever the database announces a change to cached guild data,
+    /// until `shutdown` is cancelled. Triggers on the cached tables send these for every write,
+    /// so this picks up changes made by other processes, or by hand.
+    pub async fn invalidation_job(&self, shutdown: CancellationToken) {
+        let mut listener = loop {
+            match self.listen_for_invalidations().await {
+                Ok(listener) => break listener,
+                Err(source) => {
+                    error!(?source, "Could not listen for cache invalidations");
+                    tokio::select! {
+                        () = shutdown.cancelled() => return,
+                        () = tokio::time::sleep(RELISTEN_DELAY) => {}
+                    }
+                }
+            }
+        };
+        loop {
+            let notification = tokio::select! {
+                () = shutdown.cancelled() => break,
+                notification = listener.try_recv() => notification,
+            };
+            match notification {
+                Ok(Some(notification)) => {
+                    match notification.payload().parse::<CacheInvalidation>() {
+                        Ok(CacheInvalidation { kind, guild }) => {
+                            self.invalidate(kind, guild).await;
+                        }
+                        Err(source) => warn!(?source, "Got invalid cache invalidation"),
+                    }
+                }
+                // the listener reconnects on the next call, but anything sent in between is lost
+                Ok(None) => {
+                    warn!("Lost connection while listening for cache invalidations");
+                    if let Err(source) = self.clear_caches() {
+                        error!(?source, "Could not clear caches");
+                    }
+                }
+                Err(source) => {
+                    error!(?source, "Could not receive cache invalidation");
+                    tokio::select! {
+                        () = shutdown.cancelled() => break,
+                        () = tokio::time::sleep(RELISTEN_DELAY) => {}
+                    }
+                }
+            }
+        }
+    }
+
+    async fn listen_for_invalidations(&self) -> Result<PgListener, Error> {
+        let mut listener = PgListener::connect_with(&self.db).await?;
+        listener.listen(CACHE_INVALIDATION_CHANNEL).await?;
+        Ok(listener)
+    }
+
+    /// Forget every cached guild's data, for when we may have missed invalidations.
+    fn clear_caches(&self) -> Result<(), Error> {
+        self.configs.write()?.clear();
+        self.rewards.write()?.clear();
+        self.prestige_rewards.write()?.clear();
+        self.channel_rules.write()?.clear();
+        self.role_rules.write()?.clear();
+        self.level_up_messages.write()?.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+use std::time::Duration;
+
+use sqlx::query;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+use twilight_model::id::{
+    marker::{GuildMarker, RoleMarker, UserMarker},
+    Id,
+};
+use xpd_common::{db_to_id, id_to_db, is_not_found, RankReward};
+
+use crate::{Error, XpdListenerInner};
+
+/// How often rank rewards are moved to the current top members
+const RANK_REWARD_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Who should gain and lose a rank reward role.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RankRoleChanges {
+    pub add: Vec<Id<UserMarker>>,
+    pub remove: Vec<Id<UserMarker>>,
+}
+
+impl RankRoleChanges {
+    /// `leaderboard` must be ordered the same way as `/leaderboard`, best first.
+    pub fn new(
+        holders: &[Id<UserMarker>],
+        leaderboard: &[Id<UserMarker>],
+        max_rank: usize,
+    ) -> Self {
+        let top = &leaderboard[..max_rank.min(leaderboard.len())];
+        Self {
+            add: top
+                .iter()
+                .filter(|user| !holders.contains(user))
+                .copied()
+                .collect(),
+            remove: holders
+                .iter()
+                .filter(|user| !top.contains(user))
+                .copied()
+                .collect(),
+        }
+    }
+}
+
+impl XpdListenerInner {
+    /// Move rank reward roles to whoever is currently at the top of each
+    /// leaderboard, every [`RANK_REWARD_INTERVAL`], until `shutdown` is cancelled.
+    pub async fn rank_rewards_job(&self, shutdown: CancellationToken) {
+        let mut interval = tokio::time::interval(RANK_REWARD_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                () = shutdown.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            if let Err(source) = self.update_all_rank_rewards().await {
+                error!(?source, "Could not update rank rewards");
+            }
+        }
+    }
+
+    async fn update_all_rank_rewards(&self) -> Result<(), Error> {
+        let guilds = query!(
+            "SELECT guild AS \"guild!\" FROM rank_rewards \
+                UNION SELECT guild FROM rank_reward_holders"
+        )
+        .fetch_all(&self.db)
+        .await?;
+        for guild in guilds {
+            let guild_id: Id<GuildMarker> = db_to_id(guild.guild);
+            if let Err(source) = self.update_rank_rewards(guild_id).await {
+                warn!(
+                    ?source,
+                    ?guild_id,
+                    "Could not update rank rewards for guild"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn update_rank_rewards(&self, guild_id: Id<GuildMarker>) -> Result<(), Error> {
+        self.remove_orphaned_rank_rewards(guild_id).await?;
+
+        let rewards: Vec<RankReward> = query!(
+            "SELECT id, max_rank FROM rank_rewards WHERE guild = $1",
+            id_to_db(guild_id)
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|row| RankReward {
+            id: db_to_id(row.id),
+            max_rank: row.max_rank,
+        })
+        .collect();
+        let Some(deepest) = rewards.iter().map(|reward| reward.max_rank).max() else {
+            return Ok(());
+        };
+
+        // this must stay in the same order as the leaderboard
+        let leaderboard: Vec<Id<UserMarker>> = query!(
+            "SELECT id FROM levels WHERE guild = $1 ORDER BY prestige DESC, xp DESC LIMIT $2",
+            id_to_db(guild_id),
+            deepest
+        )
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|row| db_to_id(row.id))
+        .collect();
+
+        for reward in rewards {
+            let holders: Vec<Id<UserMarker>> = query!(
+                "SELECT id FROM rank_reward_holders WHERE role = $1 AND guild = $2",
+                id_to_db(reward.id),
+                id_to_db(guild_id)
+            )
+            .fetch_all(&self.db)
+            .await?
+            .into_iter()
+            .map(|row| db_to_id(row.id))
+            .collect();
+            let changes = RankRoleChanges::new(
+                &holders,
+                &leaderboard,
+                reward.max_rank.try_into().unwrap_or(0),
+            );
+            if changes.add.is_empty() && changes.remove.is_empty() {
+                continue;
+            }
+            debug!(?guild_id, role = ?reward.id, ?changes, "Moving rank reward");
+            self.apply_rank_role_changes(guild_id, reward.id, changes)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Take removed rank rewards away from whoever we last gave them to.
+    async fn remove_orphaned_rank_rewards(&self, guild_id: Id<GuildMarker>) -> Result<(), Error> {
+        let orphans = query!(
+            "SELECT role, array_agg(id) AS \"holders!\" FROM rank_reward_holders holder \
+                WHERE guild = $1 AND NOT EXISTS \
+                (SELECT 1 FROM rank_rewards WHERE id = holder.role AND guild = holder.guild) \
+                GROUP BY role",
+            id_to_db(guild_id)
+        )
+        .fetch_all(&self.db)
+        .await?;
+        for orphan in orphans {
+            let role_id: Id<RoleMarker> = db_to_id(orphan.role);
+            let changes = RankRoleChanges {
+                add: Vec::new(),
+                remove: orphan.holders.into_iter().map(db_to_id).collect(),
+            };
+            debug!(?guild_id, role = ?role_id, ?changes, "Taking away removed rank reward");
+            self.apply_rank_role_changes(guild_id, role_id, changes)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn apply_rank_role_changes(
+        &self,
+        guild_id: Id<GuildMarker>,
+        role_id: Id<RoleMarker>,
+        changes: RankRoleChanges,
+    ) -> Result<(), Error> {
+        for user_id in changes.remove {
+            match self
+                .http
+                .remove_guild_member_role(guild_id, user_id, role_id)
+                .await
+            {
+                // members who left don't have the role anymore either
+                Ok(_) => {}
+                Err(source) if is_not_found(&source) => {}
+                Err(source) => return Err(source.into()),
+            }
+            query!(
+                "DELETE FROM rank_reward_holders WHERE role = $1 AND guild = $2 AND id = $3",
+                id_to_db(role_id),
+                id_to_db(guild_id),
+                id_to_db(user_id)
+            )
+            .execute(&self.db)
+            .await?;
+        }
+        for user_id in changes.add {
+            match self
+                .http
+                .add_guild_member_role(guild_id, user_id, role_id)
+                .await
+            {
+                Ok(_) => {}
+                // they keep their leaderboard spot, but can't get the role until they come back
+                Err(source) if is_not_found(&source) => continue,
+                Err(source) => return Err(source.into()),
+            }
+            query!(
+                "INSERT INTO rank_reward_holders (role, guild, id) VALUES ($1, $2, $3) \
+                    ON CONFLICT DO NOTHING",
+                id_to_db(role_id),
+                id_to_db(guild_id),
+                id_to_db(user_id)
+            )
+            .execute(&self.db)
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRST: Id<UserMarker> = Id::new(1);
+    const SECOND: Id<UserMarker> = Id::new(2);
+    const THIRD: Id<UserMarker> = Id::new(3);
+
+    #[test]
+    fn first_run_gives_top_members() {
+        let changes = RankRoleChanges::new(&[], &[FIRST, SECOND, THIRD], 2);
+        assert_eq!(changes.add, [FIRST, SECOND]);
+        assert!(changes.remove.is_empty());
+    }
+
+    #[test]
+    fn moves_role_when_rankings_change() {
+        let changes = RankRoleChanges::new(&[FIRST, SECOND], &[THIRD, FIRST, SECOND], 2);
+        assert_eq!(changes.add, [THIRD]);
+        assert_eq!(changes.remove, [SECOND]);
+    }
+
+    #[test]
+    fn short_leaderboards() {
+        let changes = RankRoleChanges::new(&[FIRST], &[FIRST], 10);
+        assert_eq!(changes, RankRoleChanges::default());
+    }
+}
@@ -0,0 +1,631 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use sqlx::query;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+use twilight_model::{
+    gateway::ShardId,
+    guild::Guild,
+    id::{
+        marker::{ChannelMarker, GuildMarker, RoleMarker, UserMarker},
+        Id,
+    },
+    voice::VoiceState,
+};
+use xpd_common::{db_to_id, id_to_db, GuildConfig, DEFAULT_VOICE_XP_PER_MINUTE};
+
+use crate::{Error, XpdListenerInner};
+
+const MINUTE: Duration = Duration::from_secs(60);
+/// How often voice XP is paid out to members still in a call
+const VOICE_FLUSH_INTERVAL: Duration = MINUTE;
+
+/// What we know about a member's voice connection from the gateway.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoiceStatus {
+    pub channel: Id<ChannelMarker>,
+    pub bot: bool,
+    pub muted: bool,
+    pub deafened: bool,
+}
+
+impl VoiceStatus {
+    fn from_state(state: &VoiceState, bot: bool) -> Option<Self> {
+        Some(Self {
+            channel: state.channel_id?,
+            bot,
+            // suppressed members are stage audience, and can't talk either
+            muted: state.mute || state.self_mute || state.suppress,
+            deafened: state.deaf || state.self_deaf,
+        })
+    }
+
+    /// Whether this member counts as company for someone else in the channel
+    const fn listening(&self) -> bool {
+        !self.bot && !self.deafened
+    }
+}
+
+#[derive(Debug)]
+struct VoiceSession {
+    status: VoiceStatus,
+    /// When this member last started earning XP, if they currently are
+    active_since: Option<Instant>,
+    /// Time spent active which has not been paid out yet, always under a minute after settling
+    unpaid: Duration,
+}
+
+/// Minutes of voice activity a member has earned XP for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoiceAward {
+    pub user: Id<UserMarker>,
+    pub channel: Id<ChannelMarker>,
+    pub minutes: u64,
+}
+
+/// A member's voice session, for saving across restarts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SavedVoiceSession {
+    pub guild: Id<GuildMarker>,
+    pub user: Id<UserMarker>,
+    pub afk_channel: Option<Id<ChannelMarker>>,
+    pub status: VoiceStatus,
+    /// Time spent active which has not been paid out yet
+    pub unpaid: Duration,
+}
+
+/// Every voice session in a guild.
+///
+/// A member is active, and earns XP, while they are not in the AFK channel, are
+/// neither muted nor deafened, and share their channel with at least one other
+/// human who isn't deafened. Time is only ever paid out in whole minutes, and
+/// leftover seconds are lost when the member disconnects.
+#[derive(Debug, Default)]
+pub struct GuildVoice {
+    afk_channel: Option<Id<ChannelMarker>>,
+    sessions: HashMap<Id<UserMarker>, VoiceSession>,
+}
+
+impl GuildVoice {
+    pub fn new(afk_channel: Option<Id<ChannelMarker>>) -> Self {
+        Self {
+            afk_channel,
+            sessions: HashMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    pub fn set_afk_channel(&mut self, afk_channel: Option<Id<ChannelMarker>>) {
+        self.afk_channel = afk_channel;
+    }
+
+    /// Apply a member's new voice status (or `None` if they left voice), and pay
+    /// out everything earned in this guild up to `now`.
+    pub fn update(
+        &mut self,
+        user: Id<UserMarker>,
+        status: Option<VoiceStatus>,
+        now: Instant,
+    ) -> Vec<VoiceAward> {
+        let awards = self.settle(now);
+        match status {
+            Some(status) => {
+                self.sessions
+                    .entry(user)
+                    .and_modify(|session| session.status = status)
+                    .or_insert(VoiceSession {
+                        status,
+                        active_since: None,
+                        unpaid: Duration::ZERO,
+                    });
+            }
+            None => {
+                self.sessions.remove(&user);
+            }
+        }
+        self.refresh(now);
+        awards
+    }
+
+    /// Pay out everything earned up to `now`, without changing anyone's status.
+    pub fn flush(&mut self, now: Instant) -> Vec<VoiceAward> {
+        let awards = self.settle(now);
+        self.refresh(now);
+        awards
+    }
+
+    /// Every session in this guild, counting active time up to `now` as unpaid.
+    pub fn snapshot(
+        &self,
+        guild: Id<GuildMarker>,
+        now: Instant,
+    ) -> impl Iterator<Item = SavedVoiceSession> + '_ {
+        self.sessions.iter().map(move |(user, session)| {
+            let running = session
+                .active_since
+                .map_or(Duration::ZERO, |since| now.saturating_duration_since(since));
+            SavedVoiceSession {
+                guild,
+                user: *user,
+                afk_channel: self.afk_channel,
+                status: session.status,
+                unpaid: session.unpaid + running,
+            }
+        })
+    }
+
+    /// Start tracking sessions from a [`snapshot`](Self::snapshot), as of `now`.
+    /// Sessions already being tracked win over restored ones.
+    pub fn restore(&mut self, sessions: impl IntoIterator<Item = SavedVoiceSession>, now: Instant) {
+        for saved in sessions {
+            self.afk_channel = saved.afk_channel;
+            self.sessions.entry(saved.user).or_insert(VoiceSession {
+                status: saved.status,
+                active_since: None,
+                unpaid: saved.unpaid,
+            });
+        }
+        self.refresh(now);
+    }
+
+    /// Stop every running clock and pay out whole minutes.
+    fn settle(&mut self, now: Instant) -> Vec<VoiceAward> {
+        let mut awards = Vec::new();
+        for (user, session) in &mut self.sessions {
+            if let Some(since) = session.active_since.take() {
+                session.unpaid += now.saturating_duration_since(since);
+            }
+            let minutes = session.unpaid.as_secs() / MINUTE.as_secs();
+            if minutes > 0 {
+                session.unpaid -= MINUTE * u32::try_from(minutes).unwrap_or(u32::MAX);
+                awards.push(VoiceAward {
+                    user: *user,
+                    channel: session.status.channel,
+                    minutes,
+                });
+            }
+        }
+        awards
+    }
+
+    /// Restart the clocks of everyone who is currently active.
+    fn refresh(&mut self, now: Instant) {
+        let mut listeners: HashMap<Id<ChannelMarker>, usize> = HashMap::new();
+        for session in self.sessions.values() {
+            if session.status.listening() {
+                *listeners.entry(session.status.channel).or_default() += 1;
+            }
+        }
+        for session in self.sessions.values_mut() {
+            let status = session.status;
+            let others = listeners.get(&status.channel).copied().unwrap_or(0)
+                - usize::from(status.listening());
+            let active = !status.bot
+                && !status.muted
+                && !status.deafened
+                && Some(status.channel) != self.afk_channel
+                && others > 0;
+            session.active_since = active.then_some(now);
+        }
+    }
+}
+
+impl XpdListenerInner {
+    pub async fn voice_state_update(&self, state: VoiceState) -> Result<(), Error> {
+        let Some(guild_id) = state.guild_id else {
+            return Ok(());
+        };
+        let bot = state.member.as_ref().is_some_and(|member| member.user.bot);
+        let status = VoiceStatus::from_state(&state, bot);
+        let afk_channel = self
+            .cache
+            .guild(guild_id)
+            .and_then(|guild| guild.afk_channel_id());
+
+        let awards = {
+            let mut voice = self.voice.lock()?;
+            let guild_voice = voice.entry(guild_id).or_default();
+            guild_voice.set_afk_channel(afk_channel);
+            let awards = guild_voice.update(state.user_id, status, Instant::now());
+            if guild_voice.is_empty() {
+                voice.remove(&guild_id);
+            }
+            awards
+        };
+        self.award_voice_xp(guild_id, awards).await
+    }
+
+    /// Start tracking everyone who was already in voice when we got this guild.
+    pub async fn voice_guild_create(&self, guild: &Guild) -> Result<(), Error> {
+        let now = Instant::now();
+        let mut guild_voice = GuildVoice::new(guild.afk_channel_id);
+        for state in &guild.voice_states {
+            let bot = guild
+                .members
+                .iter()
+                .find(|member| member.user.id == state.user_id)
+                .is_some_and(|member| member.user.bot);
+            if let Some(status) = VoiceStatus::from_state(state, bot) {
+                guild_voice.update(state.user_id, Some(status), now);
+            }
+        }
+
+        let old = {
+            let mut voice = self.voice.lock()?;
+            if guild_voice.is_empty() {
+                voice.remove(&guild.id)
+            } else {
+                voice.insert(guild.id, guild_voice)
+            }
+        };
+        // if we had to reconnect, don't throw away what people earned before
+        if let Some(mut old) = old {
+            self.award_voice_xp(guild.id, old.flush(now)).await?;
+        }
+        Ok(())
+    }
+
+    pub fn voice_guild_delete(&self, guild: Id<GuildMarker>) -> Result<(), Error> {
+        self.voice.lock()?.remove(&guild);
+        Ok(())
+    }
+
+    /// Save every voice session, so shards resumed by the next process know who is in voice.
+    /// Call this after [`flush_voice`](Self::flush_voice), so there's little left unpaid.
+    pub async fn save_voice(&self) -> Result<(), Error> {
+        let now = Instant::now();
+        let sessions: Vec<SavedVoiceSession> = self
+            .voice
+            .lock()?
+            .iter()
+            .flat_map(|(guild, guild_voice)| guild_voice.snapshot(*guild, now))
+            .collect();
+        let mut guilds = Vec::with_capacity(sessions.len());
+        let mut users = Vec::with_capacity(sessions.len());
+        let mut channels = Vec::with_capacity(sessions.len());
+        let mut afk_channels = Vec::with_capacity(sessions.len());
+        let mut bots = Vec::with_capacity(sessions.len());
+        let mut muted = Vec::with_capacity(sessions.len());
+        let mut deafened = Vec::with_capacity(sessions.len());
+        let mut unpaid = Vec::with_capacity(sessions.len());
+        for session in &sessions {
+            guilds.push(id_to_db(session.guild));
+            users.push(id_to_db(session.user));
+            channels.push(id_to_db(session.status.channel));
+            afk_channels.push(session.afk_channel.map(id_to_db));
+            bots.push(session.status.bot);
+            muted.push(session.status.muted);
+            deafened.push(session.status.deafened);
+            unpaid.push(i64::try_from(session.unpaid.as_millis()).unwrap_or(i64::MAX));
+        }
+        query!(
+            "INSERT INTO voice_sessions \
+                (guild, id, channel, afk_channel, bot, muted, deafened, unpaid) \
+                SELECT * FROM UNNEST($1::INT8[], $2::INT8[], $3::INT8[], $4::INT8[], \
+                $5::BOOLEAN[], $6::BOOLEAN[], $7::BOOLEAN[], $8::INT8[]) \
+                ON CONFLICT (guild, id) DO UPDATE SET channel = excluded.channel, \
+                afk_channel = excluded.afk_channel, bot = excluded.bot, muted = excluded.muted, \
+                deafened = excluded.deafened, unpaid = excluded.unpaid, saved_at = NOW()",
+            &guilds,
+            &users,
+            &channels,
+            &afk_channels as &[Option<i64>],
+            &bots,
+            &muted,
+            &deafened,
+            &unpaid
+        )
+        .execute(&self.db)
+        .await?;
+        debug!(count = sessions.len(), "Saved voice sessions");
+        Ok(())
+    }
+
+    /// Take the voice sessions saved by [`save_voice`](Self::save_voice) for guilds on `shards`,
+    /// which must all have the same total, and start tracking the ones on shards which are being
+    /// `resumed`. Sessions saved too long ago are thrown out.
+    pub async fn restore_voice(
+        &self,
+        shards: &[ShardId],
+        resumed: impl Fn(ShardId) -> bool,
+    ) -> Result<(), Error> {
+        let Some(total) = shards.first().map(|id| id.total()) else {
+            return Ok(());
+        };
+        let numbers: Vec<i64> = shards.iter().map(|id| i64::from(id.number())).collect();
+        let rows = query!(
+            "DELETE FROM voice_sessions WHERE (guild >> 22) % $1 = ANY($2) \
+                RETURNING guild, id, channel, afk_channel, bot, muted, deafened, unpaid, \
+                saved_at > NOW() - INTERVAL '5 minutes' AS \"fresh!\"",
+            i64::from(total),
+            &numbers
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut restored: HashMap<Id<GuildMarker>, Vec<SavedVoiceSession>> = HashMap::new();
+        for row in rows.into_iter().filter(|row| row.fresh) {
+            let guild: Id<GuildMarker> = db_to_id(row.guild);
+            let Ok(number) = u32::try_from((guild.get() >> 22) % u64::from(total)) else {
+                continue;
+            };
+            if !resumed(ShardId::new(number, total)) {
+                continue;
+            }
+            restored.entry(guild).or_default().push(SavedVoiceSession {
+                guild,
+                user: db_to_id(row.id),
+                afk_channel: row.afk_channel.map(db_to_id),
+                status: VoiceStatus {
+                    channel: db_to_id(row.channel),
+                    bot: row.bot,
+                    muted: row.muted,
+                    deafened: row.deafened,
+                },
+                unpaid: Duration::from_millis(row.unpaid.try_into().unwrap_or(0)),
+            });
+        }
+
+        let now = Instant::now();
+        let mut voice = self.voice.lock()?;
+        let count: usize = restored.values().map(Vec::len).sum();
+        for (guild, sessions) in restored {
+            voice.entry(guild).or_default().restore(sessions, now);
+        }
+        debug!(count, "Restored voice sessions");
+        Ok(())
+    }
+
+    /// Pay out voice XP every [`VOICE_FLUSH_INTERVAL`], until `shutdown` is cancelled,
+    /// so members see XP for long calls before they leave them.
+    pub async fn voice_flush_job(&self, shutdown: CancellationToken) {
+        let mut interval = tokio::time::interval(VOICE_FLUSH_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                () = shutdown.cancelled() => break,
+                _ = interval.tick() => {}
+            }
+            if let Err(source) = self.flush_voice().await {
+                error!(?source, "Could not pay out voice XP");
+            }
+        }
+    }
+
+    /// Pay out all voice XP earned so far, periodically and before shutting down.
+    pub async fn flush_voice(&self) -> Result<(), Error> {
+        let now = Instant::now();
+        let awards: Vec<(Id<GuildMarker>, Vec<VoiceAward>)> = self
+            .voice
+            .lock()?
+            .iter_mut()
+            .map(|(guild, guild_voice)| (*guild, guild_voice.flush(now)))
+            .collect();
+        for (guild, awards) in awards {
+            if let Err(source) = self.award_voice_xp(guild, awards).await {
+                error!(?guild, ?source, "Could not pay out voice XP for guild");
+            }
+        }
+        Ok(())
+    }
+
+    async fn award_voice_xp(
+        &self,
+        guild_id: Id<GuildMarker>,
+        awards: Vec<VoiceAward>,
+    ) -> Result<(), Error> {
+        if awards.is_empty() {
+            return Ok(());
+        }
+        let guild_config = self.get_guild_config(guild_id).await?;
+        let per_minute = guild_config
+            .voice_xp_per_minute
+            .unwrap_or(DEFAULT_VOICE_XP_PER_MINUTE);
+        if per_minute <= 0 {
+            return Ok(());
+        }
+
+        // these minutes are already flushed, so one failure mustn't lose everyone else's
+        for award in awards {
+            if let Err(source) = self
+                .award_voice_minutes(guild_id, award, per_minute, &guild_config)
+                .await
+            {
+                error!(?guild_id, ?award, ?source, "Could not award voice XP");
+            }
+        }
+        Ok(())
+    }
+
+    async fn award_voice_minutes(
+        &self,
+        guild_id: Id<GuildMarker>,
+        award: VoiceAward,
+        per_minute: i16,
+        guild_config: &GuildConfig,
+    ) -> Result<(), Error> {
+        let roles: Option<Vec<Id<RoleMarker>>> = self
+            .cache
+            .member(guild_id, award.user)
+            .map(|member| member.roles().to_vec());
+        let multiplier = self
+            .message_multiplier(
+                guild_id,
+                award.channel,
+                roles.as_deref().unwrap_or_default(),
+            )
+            .await?
+            * self
+                .prestige_multiplier(guild_id, award.user, guild_config)
+                .await?;
+        let xp_added =
+            (award.minutes as f64 * f64::from(per_minute) * f64::from(multiplier)).round() as i64;
+        if xp_added <= 0 {
+            return Ok(());
+        }
+        debug!(user = ?award.user, channel = ?award.channel, minutes = award.minutes, xp_added, "Awarding voice XP");
+        let record = query!(
+            "INSERT INTO levels (id, xp, guild) VALUES ($1, $2, $3) \
+                ON CONFLICT (id, guild) \
+                DO UPDATE SET xp=levels.xp+excluded.xp \
+                RETURNING xp, prestige",
+            id_to_db(award.user),
+            xp_added,
+            id_to_db(guild_id)
+        )
+        .fetch_one(&self.db)
+        .await?;
+
+        // we can only sync roles for members we know the roles of
+        if let Some(roles) = roles {
+            let level = mee6::LevelInfo::new(
+                &*guild_config.level_curve(),
+                u64::try_from(record.xp).unwrap_or(0),
+            )
+            .level();
+            self.sync_reward_roles(
+                guild_id,
+                award.user,
+                &roles,
+                level.try_into().unwrap_or(-1),
+                record.prestige,
+                guild_config,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANNEL: Id<ChannelMarker> = Id::new(1);
+    const AFK: Id<ChannelMarker> = Id::new(2);
+    const ALICE: Id<UserMarker> = Id::new(10);
+    const BOB: Id<UserMarker> = Id::new(11);
+    const ROBOT: Id<UserMarker> = Id::new(12);
+
+    fn talking(channel: Id<ChannelMarker>) -> Option<VoiceStatus> {
+        Some(VoiceStatus {
+            channel,
+            bot: false,
+            muted: false,
+            deafened: false,
+        })
+    }
+
+    fn minutes(awards: &[VoiceAward], user: Id<UserMarker>) -> u64 {
+        awards
+            .iter()
+            .filter(|award| award.user == user)
+            .map(|award| award.minutes)
+            .sum()
+    }
+
+    #[test]
+    fn alone_earns_nothing() {
+        let start = Instant::now();
+        let mut voice = GuildVoice::default();
+        voice.update(ALICE, talking(CHANNEL), start);
+        let awards = voice.update(ALICE, None, start + MINUTE * 10);
+        assert!(awards.is_empty());
+    }
+
+    #[test]
+    fn pairs_earn_per_minute() {
+        let start = Instant::now();
+        let mut voice = GuildVoice::default();
+        voice.update(ALICE, talking(CHANNEL), start);
+        voice.update(BOB, talking(CHANNEL), start);
+        let awards = voice.flush(start + MINUTE * 5 + Duration::from_secs(30));
+        assert_eq!(minutes(&awards, ALICE), 5);
+        assert_eq!(minutes(&awards, BOB), 5);
+
+        // the leftover 30 seconds carry over into the next payout
+        let awards = voice.update(BOB, None, start + MINUTE * 6);
+        assert_eq!(minutes(&awards, ALICE), 1);
+        assert_eq!(minutes(&awards, BOB), 1);
+
+        // alice is alone now
+        let awards = voice.flush(start + MINUTE * 60);
+        assert!(awards.is_empty());
+    }
+
+    #[test]
+    fn muted_members_earn_nothing_but_keep_company() {
+        let start = Instant::now();
+        let mut voice = GuildVoice::default();
+        voice.update(ALICE, talking(CHANNEL), start);
+        let mut muted = talking(CHANNEL);
+        muted.as_mut().unwrap().muted = true;
+        voice.update(BOB, muted, start);
+        let awards = voice.flush(start + MINUTE * 3);
+        assert_eq!(minutes(&awards, ALICE), 3);
+        assert_eq!(minutes(&awards, BOB), 0);
+    }
+
+    #[test]
+    fn deafened_and_bots_are_not_company() {
+        let start = Instant::now();
+        let mut voice = GuildVoice::default();
+        voice.update(ALICE, talking(CHANNEL), start);
+        let mut deafened = talking(CHANNEL);
+        deafened.as_mut().unwrap().deafened = true;
+        voice.update(BOB, deafened, start);
+        let mut robot = talking(CHANNEL);
+        robot.as_mut().unwrap().bot = true;
+        voice.update(ROBOT, robot, start);
+        assert!(voice.flush(start + MINUTE * 3).is_empty());
+    }
+
+    #[test]
+    fn afk_channel_earns_nothing() {
+        let start = Instant::now();
+        let mut voice = GuildVoice::new(Some(AFK));
+        voice.update(ALICE, talking(AFK), start);
+        voice.update(BOB, talking(AFK), start);
+        assert!(voice.flush(start + MINUTE * 3).is_empty());
+
+        // moving out of the AFK channel starts the clock
+        voice.update(ALICE, talking(CHANNEL), start + MINUTE * 3);
+        voice.update(BOB, talking(CHANNEL), start + MINUTE * 3);
+        let awards = voice.flush(start + MINUTE * 5);
+        assert_eq!(minutes(&awards, ALICE), 2);
+        assert_eq!(awards[0].channel, CHANNEL);
+    }
+
+    #[test]
+    fn restart_keeps_sessions() {
+        const GUILD: Id<GuildMarker> = Id::new(100);
+        let start = Instant::now();
+        let mut voice = GuildVoice::new(Some(AFK));
+        voice.update(ALICE, talking(CHANNEL), start);
+        voice.update(BOB, talking(CHANNEL), start);
+        let snapshot: Vec<SavedVoiceSession> = voice
+            .snapshot(GUILD, start + Duration::from_secs(30))
+            .collect();
+        assert_eq!(snapshot.len(), 2);
+        assert!(
+            snapshot
+                .iter()
+                .all(|saved| saved.unpaid == Duration::from_secs(30)
+                    && saved.afk_channel == Some(AFK))
+        );
+
+        // time between the snapshot and the restore isn't counted
+        let resumed = start + MINUTE * 5;
+        let mut restarted = GuildVoice::default();
+        restarted.restore(snapshot, resumed);
+        let awards = restarted.flush(resumed + Duration::from_secs(30));
+        assert_eq!(minutes(&awards, ALICE), 1);
+        assert_eq!(minutes(&awards, BOB), 1);
+    }
+}
@@ -9,6 +9,8 @@ fn main() {
     let context = Context {
         level: 694,
         rank: 124,
+        prestige: 0,
+        can_prestige: false,
         name: "Testy McTestington".to_string(),
         percentage: 30,
         current: 124,
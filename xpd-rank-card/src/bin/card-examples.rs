@@ -27,6 +27,8 @@ fn render_classic_l() -> Result<(), Error> {
     let context = Context {
         level: 1,
         rank: 1,
+        prestige: 0,
+        can_prestige: false,
         name: "Testy McTestington".to_string(),
         percentage: xp,
         current: xp,
@@ -42,11 +44,15 @@ fn render_classic_l() -> Result<(), Error> {
 fn render_classic_r() -> Result<(), Error> {
     let state = new_state();
     let xp = 51;
-    let mut customizations = Customizations::default();
-    customizations.toy = Some("cow.png".to_string());
+    let customizations = Customizations {
+        toy: Some("cow.png".to_string()),
+        ..Customizations::default()
+    };
     let context = Context {
         level: 1,
         rank: 1,
+        prestige: 2,
+        can_prestige: true,
         name: "Testy McTestington".to_string(),
         percentage: xp,
         current: xp,
@@ -69,6 +75,8 @@ fn render_vertical() -> Result<(), Error> {
     let context = Context {
         level: 420,
         rank: 100_000,
+        prestige: 3,
+        can_prestige: false,
         name: "Testy McTestington".to_string(),
         percentage: xp,
         current: xp,
@@ -92,6 +100,8 @@ fn render_vertical_procedural() {
             let context = Context {
                 level: 69,
                 rank: 1_000_000,
+                prestige: 0,
+                can_prestige: false,
                 name: "Testy McTestington".to_string(),
                 percentage: xp,
                 current: xp,
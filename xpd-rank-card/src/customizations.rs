@@ -152,6 +152,12 @@ impl Color {
     pub const fn new(red: u8, green: u8, blue: u8) -> Self {
         Self { red, green, blue }
     }
+
+    /// This color as a `0xRRGGBB` integer, like Discord uses for embeds
+    #[must_use]
+    pub const fn as_u32(&self) -> u32 {
+        (self.red as u32) << 16 | (self.green as u32) << 8 | self.blue as u32
+    }
 }
 
 impl std::fmt::Display for Color {
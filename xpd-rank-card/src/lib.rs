@@ -23,6 +23,10 @@ pub struct Context {
     pub level: u64,
     /// Rank of the user for display
     pub rank: i64,
+    /// Prestige tier of the user, 0 if they haven't prestiged
+    pub prestige: u64,
+    /// Whether the user has reached the level they can prestige at
+    pub can_prestige: bool,
     /// Username
     pub name: String,
     /// Percentage of the way to the next level, out of 100
@@ -176,7 +180,7 @@ impl InnerSvgState {
                 resolve_string,
             },
             image_rendering: ImageRendering::OptimizeSpeed,
-            font_family: context.customizations.font.to_string(),
+            font_family: context.customizations.font.clone(),
             fontdb: self.fontdb.clone(),
             ..Default::default()
         };
@@ -214,18 +218,9 @@ fn int_humanize(v: &Value, _hm: &HashMap<String, Value>) -> tera::Result<Value>
     } else {
         return Ok(v.clone());
     };
-    let (suffix, xp) = if (1_000.0..1_000_000.0).contains(&num) {
-        ("k", num / 1_000.0)
-    } else if (1_000_000.0..1_000_000_000.0).contains(&num) {
-        ("m", num / 1_000_000.0)
-    } else if (1_000_000_000.0..1_000_000_000_000.0).contains(&num) {
-        ("b", num / 1_000_000_000.0)
-    } else {
-        ("", num)
-    };
-    let xp_untrim = format!("{xp:.1}");
-    let xp_trim = xp_untrim.trim_end_matches(".0");
-    Ok(Value::String(format!("{xp_trim}{suffix}")))
+    Ok(Value::String(
+        simpleinterpolation::filters::humanize_number(num),
+    ))
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -24,7 +24,7 @@ pub async fn process_admin(
 ) -> Result<XpdSlashResponse, Error> {
     if guild_id != state.control_guild {
         return Err(Error::NotControlGuild);
-    };
+    }
     if !state.owners.contains(&invoker) {
         return Err(Error::NotControlUser);
     }
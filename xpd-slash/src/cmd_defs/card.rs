@@ -1,4 +1,5 @@
-#![allow(clippy::module_name_repetitions)]
+// `CommandModel` derives for autocomplete models emit a `continue` clippy doesn't like
+#![allow(clippy::module_name_repetitions, clippy::needless_continue)]
 use twilight_interactions::command::{
     AutocompleteValue, CommandModel, CommandOption, CreateCommand, CreateOption, ResolvedUser,
 };
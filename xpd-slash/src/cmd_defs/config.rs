@@ -1,5 +1,11 @@
-use twilight_interactions::command::{CommandModel, CreateCommand};
-use twilight_model::application::interaction::InteractionChannel;
+use twilight_interactions::command::{CommandModel, CommandOption, CreateCommand, CreateOption};
+use twilight_model::{
+    application::interaction::InteractionChannel,
+    id::{marker::ChannelMarker, Id},
+};
+use xpd_common::XpAwardStrategy;
+
+use crate::cmd_defs::card::ColorOption;
 
 #[derive(CommandModel, CreateCommand)]
 #[command(
@@ -19,13 +25,13 @@ pub struct ConfigCommandLevels {
     #[command(desc = "Enable push notifications to users when they level up and are mentioned")]
     pub ping_users: Option<bool>,
     #[command(
-        desc = "Maximum amount of XP per message (Default 25)",
+        desc = "Maximum amount of XP per message, unless it's based on length alone (Default 25)",
         min_value = 0,
         max_value = 32767
     )]
     pub max_xp_per_message: Option<i64>,
     #[command(
-        desc = "Minimum amount of XP per message (Default 15)",
+        desc = "Minimum amount of XP per message, unless it's based on length alone (Default 15)",
         min_value = 0,
         max_value = 32767
     )]
@@ -36,6 +42,122 @@ pub struct ConfigCommandLevels {
         max_value = 32767
     )]
     pub message_cooldown: Option<i64>,
+    #[command(desc = "How XP per message is calculated (Default based on message length)")]
+    pub xp_award_strategy: Option<XpAwardStrategyOption>,
+    #[command(
+        desc = "XP per minute spent talking in voice channels (Default 0, disabled)",
+        min_value = 0,
+        max_value = 32767
+    )]
+    pub voice_xp_per_minute: Option<i64>,
+    #[command(desc = "Send level-up messages as an embed")]
+    pub level_up_embed: Option<bool>,
+    #[command(
+        desc = "Title of level-up embeds. Uses the same variables as the message",
+        max_length = 256,
+        min_length = 1
+    )]
+    pub level_up_embed_title: Option<String>,
+    #[command(desc = "Hex color of level-up embeds, like #333366")]
+    pub level_up_embed_color: Option<ColorOption>,
+    #[command(desc = "Show the member's avatar in level-up embeds")]
+    pub level_up_embed_thumbnail: Option<bool>,
+}
+
+#[derive(CommandOption, CreateOption)]
+pub enum XpAwardStrategyOption {
+    #[option(name = "Random between minimum and maximum", value = "uniform")]
+    UniformRange,
+    #[option(name = "Based on message length", value = "length")]
+    MessageLength,
+    #[option(name = "Random, scaled by message length", value = "hybrid")]
+    Hybrid,
+}
+
+impl From<XpAwardStrategyOption> for XpAwardStrategy {
+    fn from(value: XpAwardStrategyOption) -> Self {
+        match value {
+            XpAwardStrategyOption::UniformRange => Self::UniformRange,
+            XpAwardStrategyOption::MessageLength => Self::MessageLength,
+            XpAwardStrategyOption::Hybrid => Self::Hybrid,
+        }
+    }
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "curve",
+    desc = "Configure how much XP each level takes",
+    dm_permission = false
+)]
+pub struct ConfigCommandCurve {
+    #[command(desc = "Shape of the level curve (Default MEE6)")]
+    pub curve: LevelCurveOption,
+    #[command(
+        desc = "XP per level for linear curves, or XP for level 1 of exponential curves",
+        min_value = 1
+    )]
+    pub xp: Option<i64>,
+    #[command(
+        desc = "How many times more XP each level of an exponential curve takes than the last",
+        min_value = 1,
+        max_value = 10
+    )]
+    pub growth: Option<f64>,
+    #[command(
+        desc = "Total XP needed for each level of a table curve, like 100, 250, 500",
+        max_length = 2000,
+        min_length = 1
+    )]
+    pub table: Option<String>,
+    #[command(desc = "Stop MEE6 curves getting steeper past a certain level (Default true)")]
+    pub wall: Option<bool>,
+    #[command(
+        desc = "Level the XP wall of MEE6 curves starts at (Default 30)",
+        min_value = 1
+    )]
+    pub wall_start: Option<i64>,
+    #[command(
+        desc = "XP per level past the wall (Default the XP needed to reach the wall)",
+        min_value = 1
+    )]
+    pub wall_slope: Option<i64>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "prestige",
+    desc = "Let members reset their XP for a prestige tier",
+    dm_permission = false
+)]
+pub struct ConfigCommandPrestige {
+    #[command(desc = "Allow members to use /prestige (Default false)")]
+    pub enabled: Option<bool>,
+    #[command(desc = "Level members can prestige at (Default 100)", min_value = 1)]
+    pub level: Option<i64>,
+    #[command(
+        desc = "XP multiplier gained per prestige tier, like 1.1 for +10% each time (Default 1)",
+        min_value = 1,
+        max_value = 10
+    )]
+    pub xp_multiplier: Option<f64>,
+}
+
+#[derive(CommandOption, CreateOption)]
+pub enum LevelCurveOption {
+    #[option(name = "MEE6", value = "mee6")]
+    Mee6,
+    #[option(name = "Classic MEE6, 5l² + 50l + 100 per level", value = "classic")]
+    Classic,
+    #[option(name = "Linear, the same XP for every level", value = "linear")]
+    Linear,
+    #[option(
+        name = "Exponential, each level takes more than the last",
+        value = "exponential"
+    )]
+    Exponential,
+    #[option(name = "Table, the XP for each level listed by hand", value = "table")]
+    Table,
 }
 
 #[derive(CommandModel, CreateCommand)]
@@ -49,6 +171,118 @@ pub struct ConfigCommandRewards {
     pub one_at_a_time: Option<bool>,
 }
 
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "channels",
+    desc = "Configure XP multipliers for channels and categories",
+    dm_permission = false
+)]
+pub enum ConfigCommandChannels {
+    #[command(name = "set")]
+    Set(ConfigCommandChannelsSet),
+    #[command(name = "remove")]
+    Remove(ConfigCommandChannelsRemove),
+    #[command(name = "list")]
+    List(ConfigCommandChannelsList),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "set",
+    desc = "Set the XP multiplier for a channel, category, or thread parent",
+    dm_permission = false
+)]
+pub struct ConfigCommandChannelsSet {
+    #[command(
+        desc = "Channel or category to set the multiplier of",
+        channel_types = "guild_text guild_voice guild_category guild_announcement guild_forum \
+                         guild_stage_voice guild_media"
+    )]
+    pub channel: InteractionChannel,
+    #[command(
+        desc = "What to multiply XP by in this channel. 0 disables XP here",
+        min_value = 0,
+        max_value = 10
+    )]
+    pub multiplier: f64,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "remove",
+    desc = "Remove the XP multiplier for a channel or category",
+    dm_permission = false
+)]
+pub struct ConfigCommandChannelsRemove {
+    #[command(desc = "Channel or category to remove the multiplier of")]
+    pub channel: Id<ChannelMarker>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "list",
+    desc = "Show a list of channel XP multipliers",
+    dm_permission = false
+)]
+pub struct ConfigCommandChannelsList;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "messages",
+    desc = "Configure extra level-up messages",
+    dm_permission = false
+)]
+pub enum ConfigCommandMessages {
+    #[command(name = "add")]
+    Add(ConfigCommandMessagesAdd),
+    #[command(name = "remove")]
+    Remove(ConfigCommandMessagesRemove),
+    #[command(name = "list")]
+    List(ConfigCommandMessagesList),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "add",
+    desc = "Add a level-up message, picked at random or used at one specific level",
+    dm_permission = false
+)]
+pub struct ConfigCommandMessagesAdd {
+    #[command(
+        desc = "Message to send when a user levels up. https://xp.valk.sh/docs/",
+        max_length = 512,
+        min_length = 1
+    )]
+    pub message: String,
+    #[command(
+        desc = "Always use this message at this level, instead of a random one",
+        min_value = 0
+    )]
+    pub level: Option<i64>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "remove",
+    desc = "Remove an extra level-up message",
+    dm_permission = false
+)]
+pub struct ConfigCommandMessagesRemove {
+    #[command(
+        desc = "ID of the message to remove, from /config messages list",
+        min_value = 0
+    )]
+    pub id: i64,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "list",
+    desc = "Show a list of extra level-up messages",
+    dm_permission = false
+)]
+pub struct ConfigCommandMessagesList;
+
 #[derive(CommandModel, CreateCommand)]
 #[command(name = "reset", desc = "Reset your guild's configuration")]
 pub struct ConfigCommandReset;
@@ -17,10 +17,16 @@ use twilight_model::{
 pub enum XpCommandRewards {
     #[command(name = "add")]
     Add(XpCommandRewardsAdd),
+    #[command(name = "add-rank")]
+    AddRank(XpCommandRewardsAddRank),
+    #[command(name = "add-prestige")]
+    AddPrestige(XpCommandRewardsAddPrestige),
     #[command(name = "remove")]
     Remove(XpCommandRewardsRemove),
     #[command(name = "list")]
     List(XpCommandRewardsList),
+    #[command(name = "sync")]
+    Sync(XpCommandRewardsSync),
 }
 
 #[derive(CommandModel, CreateCommand)]
@@ -34,6 +40,38 @@ pub struct XpCommandRewardsAdd {
     pub level: i64,
     #[command(desc = "What role to grant", min_value = 1)]
     pub role: Role,
+    #[command(desc = "A role to take away at this level, like a newcomer role")]
+    pub remove_role: Option<Role>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "add-rank",
+    desc = "Add a reward which moves to whoever is at the top of the leaderboard",
+    dm_permission = false
+)]
+pub struct XpCommandRewardsAddRank {
+    #[command(
+        desc = "How many of the top members should have the role",
+        min_value = 1,
+        max_value = 100
+    )]
+    pub top: i64,
+    #[command(desc = "What role to grant")]
+    pub role: Role,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "add-prestige",
+    desc = "Add a reward for reaching a prestige tier",
+    dm_permission = false
+)]
+pub struct XpCommandRewardsAddPrestige {
+    #[command(desc = "What prestige tier to grant the role reward at", min_value = 1)]
+    pub tier: i64,
+    #[command(desc = "What role to grant")]
+    pub role: Role,
 }
 
 #[derive(CommandModel, CreateCommand)]
@@ -57,12 +95,73 @@ pub struct XpCommandRewardsRemove {
 )]
 pub struct XpCommandRewardsList;
 
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "sync",
+    desc = "Give and take reward roles from every member with XP, to match their level",
+    dm_permission = false
+)]
+pub struct XpCommandRewardsSync;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "multipliers",
+    desc = "Manage XP multipliers for roles",
+    dm_permission = false
+)]
+pub enum XpCommandMultipliers {
+    #[command(name = "add")]
+    Add(XpCommandMultipliersAdd),
+    #[command(name = "remove")]
+    Remove(XpCommandMultipliersRemove),
+    #[command(name = "list")]
+    List(XpCommandMultipliersList),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "add",
+    desc = "Add or change the XP multiplier for a role",
+    dm_permission = false
+)]
+pub struct XpCommandMultipliersAdd {
+    #[command(desc = "What role to multiply the XP of")]
+    pub role: Role,
+    #[command(
+        desc = "What to multiply XP by for members with this role. 0 blocks XP",
+        min_value = 0,
+        max_value = 10
+    )]
+    pub multiplier: f64,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "remove",
+    desc = "Remove the XP multiplier for a role",
+    dm_permission = false
+)]
+pub struct XpCommandMultipliersRemove {
+    #[command(desc = "What role multiplier to remove")]
+    pub role: Id<RoleMarker>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "list",
+    desc = "Show a list of role XP multipliers",
+    dm_permission = false
+)]
+pub struct XpCommandMultipliersList;
+
 #[derive(CommandModel, CreateCommand)]
 #[command(
     name = "experience",
     desc = "Manage user experience in this guild",
     dm_permission = false
 )]
+// twilight-interactions can't parse boxed subcommands, and this only lives for one interaction
+#[allow(clippy::large_enum_variant)]
 pub enum XpCommandExperience {
     #[command(name = "add")]
     Add(XpCommandExperienceAdd),
@@ -46,6 +46,14 @@ pub struct RankCommand {
     pub showoff: Option<bool>,
 }
 
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "prestige",
+    desc = "Reset your XP to gain a prestige tier",
+    dm_permission = false
+)]
+pub struct PrestigeCommand;
+
 #[derive(CommandModel, CreateCommand)]
 #[command(
     name = "admin",
@@ -101,6 +109,14 @@ pub enum ConfigCommand {
     Rewards(config::ConfigCommandRewards),
     #[command(name = "levels")]
     Levels(config::ConfigCommandLevels),
+    #[command(name = "channels")]
+    Channels(config::ConfigCommandChannels),
+    #[command(name = "messages")]
+    Messages(config::ConfigCommandMessages),
+    #[command(name = "curve")]
+    Curve(config::ConfigCommandCurve),
+    #[command(name = "prestige")]
+    Prestige(config::ConfigCommandPrestige),
 }
 
 impl ConfigCommand {
@@ -144,6 +160,8 @@ impl GuildCardCommand {
 pub enum XpCommand {
     #[command(name = "rewards")]
     Rewards(manage::XpCommandRewards),
+    #[command(name = "multipliers")]
+    Multipliers(manage::XpCommandMultipliers),
     #[command(name = "experience")]
     Experience(manage::XpCommandExperience),
 }
@@ -181,6 +199,7 @@ impl SlashState {
             GuildCardCommand::create_command().into(),
             ConfigCommand::create_command().into(),
             LeaderboardCommand::create_command().into(),
+            PrestigeCommand::create_command().into(),
             CommandBuilder::new("Get level", "", CommandType::User).build(),
             CommandBuilder::new("Get author level", "", CommandType::Message).build(),
         ];
@@ -1,16 +1,25 @@
+use std::{fmt::Write, num::NonZeroU64, ops::Range};
+
+use mee6::{Curve, Exponential, Linear, Mee6, Table, Wall};
 use simpleinterpolation::Interpolation;
 use twilight_model::{
     channel::{message::MessageFlags, ChannelType},
     id::{marker::GuildMarker, Id},
 };
 use xpd_common::{
-    id_to_db, GuildConfig, RawGuildConfig, DEFAULT_MAX_XP_PER_MESSAGE, DEFAULT_MIN_XP_PER_MESSAGE,
+    id_to_db, query_guild_config, GuildConfig, RawLevelCurve, ReinterpretPrimitiveBits,
+    XpAwardStrategy, DEFAULT_MAX_XP_PER_MESSAGE, DEFAULT_MIN_XP_PER_MESSAGE, TEMPLATE_FILTERS,
     TEMPLATE_VARIABLES,
 };
 
 use crate::{
     cmd_defs::{
-        config::{ConfigCommandLevels, ConfigCommandRewards},
+        config::{
+            ConfigCommandChannels, ConfigCommandChannelsRemove, ConfigCommandChannelsSet,
+            ConfigCommandCurve, ConfigCommandLevels, ConfigCommandMessages,
+            ConfigCommandMessagesAdd, ConfigCommandMessagesRemove, ConfigCommandPrestige,
+            ConfigCommandRewards, LevelCurveOption,
+        },
         ConfigCommand,
     },
     Error, SlashState, XpdSlashResponse,
@@ -26,6 +35,10 @@ pub async fn process_config(
         ConfigCommand::Get(_) => get_config(state, guild).await,
         ConfigCommand::Rewards(r) => process_rewards_config(state, guild, r).await,
         ConfigCommand::Levels(l) => process_levels_config(state, guild, l).await,
+        ConfigCommand::Channels(c) => process_channels_config(state, guild, c).await,
+        ConfigCommand::Messages(m) => process_messages_config(state, guild, m).await,
+        ConfigCommand::Curve(c) => process_curve_config(state, guild, c).await,
+        ConfigCommand::Prestige(p) => process_prestige_config(state, guild, p).await,
     }
     .map(|s| XpdSlashResponse::with_embed_text(s).flags(MessageFlags::EPHEMERAL))
 }
@@ -35,13 +48,12 @@ async fn process_rewards_config(
     guild_id: Id<GuildMarker>,
     options: ConfigCommandRewards,
 ) -> Result<String, Error> {
-    let config = query_as!(
-        RawGuildConfig,
+    let config = query_guild_config!(
         "INSERT INTO guild_configs (id, one_at_a_time) VALUES ($1, $2) \
             ON CONFLICT (id) DO UPDATE SET \
-            one_at_a_time = COALESCE($2, excluded.one_at_a_time) \
-            RETURNING one_at_a_time, level_up_message, level_up_channel, ping_on_level_up, \
-            max_xp_per_message, min_xp_per_message, message_cooldown",
+            one_at_a_time = COALESCE($2, guild_configs.one_at_a_time) \
+            RETURNING ",
+        "",
         id_to_db(guild_id),
         options.one_at_a_time,
     )
@@ -61,12 +73,13 @@ async fn process_levels_config(
         if interp_template.len() > 512 {
             return Err(Error::LevelUpMessageTooLong);
         }
-        let interp = Interpolation::new(interp_template.clone())?;
-        for item in interp.variables_used() {
-            if !TEMPLATE_VARIABLES.contains(&item) {
-                return Err(Error::UnknownInterpolationVariable(item.to_string()));
-            }
+        validate_template(interp_template)?;
+    }
+    if let Some(interp_template) = options.level_up_embed_title.as_ref() {
+        if interp_template.len() > 256 {
+            return Err(Error::LevelUpEmbedTitleTooLong);
         }
+        validate_template(interp_template)?;
     }
 
     if options
@@ -80,29 +93,50 @@ async fn process_levels_config(
     let max_xp_per_message = safecast_to_i16(options.max_xp_per_message)?;
     let min_xp_per_message = safecast_to_i16(options.min_xp_per_message)?;
     let message_cooldown = safecast_to_i16(options.message_cooldown)?;
+    let voice_xp_per_minute = safecast_to_i16(options.voice_xp_per_minute)?;
+    let level_up_embed_color = options
+        .level_up_embed_color
+        .map(|color| color.as_u32().reinterpret_bits());
+    let xp_award_strategy = options
+        .xp_award_strategy
+        .map(|strategy| XpAwardStrategy::from(strategy).to_db());
 
     let mut txn = state.db.begin().await?;
 
-    let config: GuildConfig = query_as!(
-        RawGuildConfig,
-        "INSERT INTO guild_configs (id, level_up_message, level_up_channel, ping_on_level_up) \
-            VALUES ($1, $2, $3, $4) \
+    let config: GuildConfig = query_guild_config!(
+        "INSERT INTO guild_configs (id, level_up_message, level_up_channel, ping_on_level_up, \
+            max_xp_per_message, min_xp_per_message, message_cooldown, xp_award_strategy, \
+            voice_xp_per_minute, level_up_embed, level_up_embed_title, level_up_embed_color, \
+            level_up_embed_thumbnail) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13) \
             ON CONFLICT (id) DO UPDATE SET \
-            level_up_message = COALESCE($2, excluded.level_up_message), \
-            level_up_channel = COALESCE($3, excluded.level_up_channel), \
-            ping_on_level_up = COALESCE($4, excluded.ping_on_level_up), \
-            max_xp_per_message = COALESCE($5, excluded.max_xp_per_message), \
-            min_xp_per_message = COALESCE($6, excluded.min_xp_per_message), \
-            message_cooldown = COALESCE($7, excluded.message_cooldown) \
-            RETURNING one_at_a_time, level_up_message, level_up_channel, ping_on_level_up, \
-            max_xp_per_message, min_xp_per_message, message_cooldown",
+            level_up_message = COALESCE($2, guild_configs.level_up_message), \
+            level_up_channel = COALESCE($3, guild_configs.level_up_channel), \
+            ping_on_level_up = COALESCE($4, guild_configs.ping_on_level_up), \
+            max_xp_per_message = COALESCE($5, guild_configs.max_xp_per_message), \
+            min_xp_per_message = COALESCE($6, guild_configs.min_xp_per_message), \
+            message_cooldown = COALESCE($7, guild_configs.message_cooldown), \
+            xp_award_strategy = COALESCE($8, guild_configs.xp_award_strategy), \
+            voice_xp_per_minute = COALESCE($9, guild_configs.voice_xp_per_minute), \
+            level_up_embed = COALESCE($10, guild_configs.level_up_embed), \
+            level_up_embed_title = COALESCE($11, guild_configs.level_up_embed_title), \
+            level_up_embed_color = COALESCE($12, guild_configs.level_up_embed_color), \
+            level_up_embed_thumbnail = COALESCE($13, guild_configs.level_up_embed_thumbnail) \
+            RETURNING ",
+        "",
         id_to_db(guild_id),
         options.level_up_message,
         options.level_up_channel.as_ref().map(|ic| id_to_db(ic.id)),
         options.ping_users,
         max_xp_per_message,
         min_xp_per_message,
-        message_cooldown
+        message_cooldown,
+        xp_award_strategy,
+        voice_xp_per_minute,
+        options.level_up_embed,
+        options.level_up_embed_title,
+        level_up_embed_color,
+        options.level_up_embed_thumbnail
     )
     .fetch_one(txn.as_mut())
     .await?
@@ -117,6 +151,363 @@ async fn process_levels_config(
     Ok(msg)
 }
 
+async fn process_channels_config(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    options: ConfigCommandChannels,
+) -> Result<String, Error> {
+    match options {
+        ConfigCommandChannels::Set(set) => process_channels_set(state, guild_id, set).await,
+        ConfigCommandChannels::Remove(rm) => process_channels_rm(state, guild_id, rm).await,
+        ConfigCommandChannels::List(_) => process_channels_list(state, guild_id).await,
+    }
+}
+
+async fn process_channels_set(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    options: ConfigCommandChannelsSet,
+) -> Result<String, Error> {
+    #[allow(clippy::cast_possible_truncation)]
+    let multiplier = options.multiplier as f32;
+    query!(
+        "INSERT INTO channel_xp_rules (id, guild, multiplier) VALUES ($1, $2, $3) \
+            ON CONFLICT (id, guild) DO UPDATE SET multiplier = excluded.multiplier",
+        id_to_db(options.channel.id),
+        id_to_db(guild_id),
+        multiplier
+    )
+    .execute(&state.db)
+    .await?;
+    state.invalidate_channel_rules(guild_id).await;
+    if multiplier == 0.0 {
+        Ok(format!("Disabled XP in <#{}>!", options.channel.id))
+    } else {
+        Ok(format!(
+            "Set XP multiplier for <#{}> to {multiplier}x!",
+            options.channel.id
+        ))
+    }
+}
+
+async fn process_channels_rm(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    options: ConfigCommandChannelsRemove,
+) -> Result<String, Error> {
+    query!(
+        "DELETE FROM channel_xp_rules WHERE id = $1 AND guild = $2",
+        id_to_db(options.channel),
+        id_to_db(guild_id)
+    )
+    .execute(&state.db)
+    .await?;
+    state.invalidate_channel_rules(guild_id).await;
+    Ok(format!("Removed XP multiplier for <#{}>!", options.channel))
+}
+
+async fn process_channels_list(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<String, Error> {
+    let mut rules = query!(
+        "SELECT id, multiplier FROM channel_xp_rules WHERE guild = $1",
+        id_to_db(guild_id)
+    )
+    .fetch_all(&state.db)
+    .await?;
+    rules.sort_by(|a, b| b.multiplier.total_cmp(&a.multiplier));
+
+    let mut data = String::new();
+    for rule in rules {
+        writeln!(data, "<#{}>: {}x", rule.id, rule.multiplier)?;
+    }
+    if data.is_empty() {
+        data = "No channel XP multipliers set for this server".to_string();
+    }
+    Ok(data)
+}
+
+async fn process_curve_config(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    options: ConfigCommandCurve,
+) -> Result<String, Error> {
+    let xp = options
+        .xp
+        .and_then(|xp| NonZeroU64::new(xp.try_into().ok()?));
+    let curve = match options.curve {
+        LevelCurveOption::Mee6 => {
+            let wall_slope = options
+                .wall_slope
+                .and_then(|slope| NonZeroU64::new(slope.try_into().ok()?));
+            let wall = if options.wall.unwrap_or(true) {
+                let start = options
+                    .wall_start
+                    .map_or(Ok(Wall::MEE6.start()), u64::try_from)?;
+                Some(Wall::new(start, wall_slope))
+            } else if options.wall_start.is_some() || wall_slope.is_some() {
+                return Err(Error::LevelCurveWallDisabled);
+            } else {
+                None
+            };
+            Curve::Mee6(Mee6::new(wall))
+        }
+        LevelCurveOption::Classic => Curve::Classic,
+        LevelCurveOption::Linear => {
+            Curve::Linear(Linear::new(xp.ok_or(Error::LevelCurveMissingXp)?))
+        }
+        LevelCurveOption::Exponential => {
+            let xp = xp.ok_or(Error::LevelCurveMissingXp)?;
+            let growth = options.growth.ok_or(Error::InvalidLevelCurveGrowth)?;
+            Curve::Exponential(Exponential::new(xp, growth).ok_or(Error::InvalidLevelCurveGrowth)?)
+        }
+        LevelCurveOption::Table => {
+            let table = options.table.ok_or(Error::InvalidLevelCurveTable)?;
+            Curve::Table(parse_level_table(&table).ok_or(Error::InvalidLevelCurveTable)?)
+        }
+    };
+    let raw = RawLevelCurve::from(&curve);
+
+    let config: GuildConfig = query_guild_config!(
+        "INSERT INTO guild_configs (id, level_curve, level_curve_xp, level_curve_growth, \
+            level_curve_table, level_curve_wall, level_curve_wall_start, level_curve_wall_slope) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8) \
+            ON CONFLICT (id) DO UPDATE SET \
+            level_curve = excluded.level_curve, \
+            level_curve_xp = excluded.level_curve_xp, \
+            level_curve_growth = excluded.level_curve_growth, \
+            level_curve_table = excluded.level_curve_table, \
+            level_curve_wall = excluded.level_curve_wall, \
+            level_curve_wall_start = excluded.level_curve_wall_start, \
+            level_curve_wall_slope = excluded.level_curve_wall_slope \
+            RETURNING ",
+        "",
+        id_to_db(guild_id),
+        raw.kind,
+        raw.xp,
+        raw.growth,
+        raw.table.as_deref(),
+        raw.wall,
+        raw.wall_start,
+        raw.wall_slope
+    )
+    .fetch_one(&state.db)
+    .await?
+    .try_into()?;
+    state.update_config(guild_id, config).await;
+
+    Ok(format!(
+        "Set the level curve to {curve}! Existing reward roles are not updated until members \
+        level up, or you run `/xp rewards sync`."
+    ))
+}
+
+async fn process_prestige_config(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    options: ConfigCommandPrestige,
+) -> Result<String, Error> {
+    #[allow(clippy::cast_possible_truncation)]
+    let xp_multiplier = options.xp_multiplier.map(|v| v as f32);
+    let config: GuildConfig = query_guild_config!(
+        "INSERT INTO guild_configs (id, prestige, prestige_level, prestige_xp_multiplier) \
+            VALUES ($1, $2, $3, $4) \
+            ON CONFLICT (id) DO UPDATE SET \
+            prestige = COALESCE($2, guild_configs.prestige), \
+            prestige_level = COALESCE($3, guild_configs.prestige_level), \
+            prestige_xp_multiplier = COALESCE($4, guild_configs.prestige_xp_multiplier) \
+            RETURNING ",
+        "",
+        id_to_db(guild_id),
+        options.enabled,
+        options.level,
+        xp_multiplier
+    )
+    .fetch_one(&state.db)
+    .await?
+    .try_into()?;
+    let message = config.prestige_level().map_or_else(
+        || "Prestige is turned off.".to_string(),
+        |level| format!("Members can now run `/prestige` once they reach level {level}!"),
+    );
+    state.update_config(guild_id, config).await;
+    Ok(message)
+}
+
+/// Parse a list of total XP requirements, like `100, 250, 500`
+fn parse_level_table(table: &str) -> Option<Table> {
+    let levels = table
+        .split([',', ' ', '\n'])
+        .filter(|item| !item.is_empty())
+        .map(str::parse)
+        .collect::<Result<Vec<u64>, _>>()
+        .ok()?;
+    // requirements are stored as signed BIGINTs
+    if levels.is_empty() || levels.iter().any(|xp| i64::try_from(*xp).is_err()) {
+        return None;
+    }
+    Table::new(levels)
+}
+
+/// How many extra level-up messages a guild can have, overrides included
+const MAX_LEVEL_UP_MESSAGES: i64 = 25;
+
+async fn process_messages_config(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    options: ConfigCommandMessages,
+) -> Result<String, Error> {
+    match options {
+        ConfigCommandMessages::Add(add) => process_messages_add(state, guild_id, add).await,
+        ConfigCommandMessages::Remove(rm) => process_messages_rm(state, guild_id, rm).await,
+        ConfigCommandMessages::List(_) => process_messages_list(state, guild_id).await,
+    }
+}
+
+async fn process_messages_add(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    options: ConfigCommandMessagesAdd,
+) -> Result<String, Error> {
+    if options.message.len() > 512 {
+        return Err(Error::LevelUpMessageTooLong);
+    }
+    validate_template(&options.message)?;
+
+    let mut txn = state.db.begin().await?;
+    let count = query!(
+        "SELECT COUNT(*) AS count FROM level_up_messages WHERE guild = $1 \
+            AND level IS DISTINCT FROM $2",
+        id_to_db(guild_id),
+        options.level
+    )
+    .fetch_one(txn.as_mut())
+    .await?
+    .count
+    .unwrap_or(0);
+    if count >= MAX_LEVEL_UP_MESSAGES {
+        return Err(Error::TooManyLevelUpMessages(MAX_LEVEL_UP_MESSAGES));
+    }
+    // level_up_messages hangs off the guild's config, so make sure it has one
+    query!(
+        "INSERT INTO guild_configs (id) VALUES ($1) ON CONFLICT (id) DO NOTHING",
+        id_to_db(guild_id)
+    )
+    .execute(txn.as_mut())
+    .await?;
+    let id = if let Some(level) = options.level {
+        query!(
+            "INSERT INTO level_up_messages (guild, level, message) VALUES ($1, $2, $3) \
+                ON CONFLICT (guild, level) WHERE level IS NOT NULL \
+                DO UPDATE SET message = excluded.message RETURNING id",
+            id_to_db(guild_id),
+            level,
+            options.message
+        )
+        .fetch_one(txn.as_mut())
+        .await?
+        .id
+    } else {
+        query!(
+            "INSERT INTO level_up_messages (guild, message) VALUES ($1, $2) RETURNING id",
+            id_to_db(guild_id),
+            options.message
+        )
+        .fetch_one(txn.as_mut())
+        .await?
+        .id
+    };
+    txn.commit().await?;
+    state.invalidate_level_up_messages(guild_id).await;
+
+    Ok(options.level.map_or_else(
+        || format!("Added level-up message #{id} to the random pool!"),
+        |level| format!("Set level-up message #{id} for level {level}!"),
+    ))
+}
+
+async fn process_messages_rm(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+    options: ConfigCommandMessagesRemove,
+) -> Result<String, Error> {
+    let deleted = query!(
+        "DELETE FROM level_up_messages WHERE id = $1 AND guild = $2",
+        options.id,
+        id_to_db(guild_id)
+    )
+    .execute(&state.db)
+    .await?
+    .rows_affected();
+    if deleted == 0 {
+        return Err(Error::UnknownLevelUpMessage);
+    }
+    state.invalidate_level_up_messages(guild_id).await;
+    Ok(format!("Removed level-up message #{}!", options.id))
+}
+
+async fn process_messages_list(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<String, Error> {
+    let messages = query!(
+        "SELECT id, level, message FROM level_up_messages WHERE guild = $1 \
+            ORDER BY level ASC NULLS LAST, id ASC",
+        id_to_db(guild_id)
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let mut data = String::new();
+    for message in messages {
+        match message.level {
+            Some(level) => write!(data, "#{} (level {level}): ", message.id)?,
+            None => write!(data, "#{} (random): ", message.id)?,
+        }
+        writeln!(data, "`{}`", message.message.replace('`', "\\`"))?;
+    }
+    if data.is_empty() {
+        data = "No extra level-up messages set for this server".to_string();
+    }
+    Ok(data)
+}
+
+/// Make sure a level-up template compiles, and only uses variables and filters we know about
+fn validate_template(template: &str) -> Result<Interpolation, Error> {
+    let interp = Interpolation::new(template).map_err(|source| Error::InvalidTemplate {
+        underline: underline(template, source.span(template)),
+        source,
+    })?;
+    for item in interp.variables_used() {
+        if !TEMPLATE_VARIABLES.contains(&item) {
+            return Err(Error::UnknownInterpolationVariable(item.to_string()));
+        }
+    }
+    for item in interp.filters_used() {
+        if !TEMPLATE_FILTERS.contains(&item) {
+            return Err(Error::UnknownInterpolationFilter(item.to_string()));
+        }
+    }
+    Ok(interp)
+}
+
+/// Show the line of `template` which `span` starts on, with carets under the part in `span`.
+fn underline(template: &str, span: Range<usize>) -> String {
+    let line_start = template[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = template[span.start..]
+        .find('\n')
+        .map_or(template.len(), |i| span.start + i);
+    let padding = template[line_start..span.start].chars().count();
+    let width = template[span.start..span.end.min(line_end)].chars().count();
+    format!(
+        "{}\n{}{}",
+        &template[line_start..line_end],
+        " ".repeat(padding),
+        "^".repeat(width.max(1))
+    )
+}
+
 fn safecast_to_i16(ou16: Option<i64>) -> Result<Option<i16>, Error> {
     ou16.map(TryInto::try_into).transpose().map_err(Into::into)
 }
@@ -129,20 +520,13 @@ async fn reset_config(state: SlashState, guild_id: Id<GuildMarker>) -> Result<St
     .execute(&state.db)
     .await?;
     state.update_config(guild_id, GuildConfig::default()).await;
+    // deleting the config cascades to the guild's extra level-up messages
+    state.invalidate_level_up_messages(guild_id).await;
     Ok("Reset guild reward config, but NOT rewards themselves!".to_string())
 }
 
 async fn get_config(state: SlashState, guild_id: Id<GuildMarker>) -> Result<String, Error> {
-    let config: GuildConfig = query_as!(
-        RawGuildConfig,
-        "SELECT one_at_a_time, level_up_message, level_up_channel, ping_on_level_up, max_xp_per_message, \
-        min_xp_per_message, message_cooldown FROM guild_configs \
-        WHERE id = $1",
-        id_to_db(guild_id),
-    )
-    .fetch_optional(&state.db)
-    .await?
-    .map_or_else(|| Ok(GuildConfig::default()), TryInto::try_into)?;
+    let config = state.get_guild_config(guild_id).await?;
     Ok(config.to_string())
 }
 
@@ -7,7 +7,10 @@ use twilight_model::{
         },
     },
     http::interaction::InteractionResponse,
-    id::{marker::GuildMarker, Id},
+    id::{
+        marker::{ChannelMarker, GuildMarker},
+        Id,
+    },
 };
 use xpd_common::MemberDisplayInfo;
 
@@ -23,12 +26,18 @@ use crate::{
 #[derive(Clone, Debug)]
 pub struct Respondable {
     token: String,
+    channel: Option<Id<ChannelMarker>>,
 }
 
 impl Respondable {
     pub fn token(&self) -> &str {
         &self.token
     }
+
+    /// The channel the interaction was used in
+    pub const fn channel(&self) -> Option<Id<ChannelMarker>> {
+        self.channel
+    }
 }
 
 pub async fn process(
@@ -38,6 +47,7 @@ pub async fn process(
     trace!(?interaction, "got interaction");
     let respondable = Respondable {
         token: interaction.token.clone(),
+        channel: interaction.channel.as_ref().map(|channel| channel.id),
     };
     let Some(data) = interaction.data else {
         return Err(Error::NoInteractionData);
@@ -193,13 +203,19 @@ async fn process_slash_cmd(
                 .await
                 .map(Into::into)
         }
-        "leaderboard" => crate::leaderboard::leaderboard(
-            state,
-            guild_id.ok_or(Error::NoGuildId)?,
-            LeaderboardCommand::from_interaction(data.into())?,
-        )
-        .await
-        .map(Into::into),
+        "leaderboard" => {
+            crate::leaderboard::leaderboard(
+                state,
+                guild_id.ok_or(Error::NoGuildId)?,
+                LeaderboardCommand::from_interaction(data.into())?,
+            )
+            .await
+        }
+        "prestige" => {
+            crate::prestige::prestige(guild_id.ok_or(Error::NoGuildId)?, invoker.id, state)
+                .await
+                .map(Into::into)
+        }
         _ => Err(Error::UnrecognizedCommand),
     }
 }
@@ -0,0 +1,267 @@
+//! An HTTP interactions endpoint, so discord can send us interactions as webhooks
+//! instead of over the gateway.
+
+use std::{net::SocketAddr, str::FromStr};
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use ed25519_dalek::{Signature, VerifyingKey};
+use tokio_util::sync::CancellationToken;
+use twilight_model::{
+    application::interaction::{Interaction, InteractionType},
+    http::interaction::{InteractionResponse, InteractionResponseData, InteractionResponseType},
+};
+
+use crate::XpdSlash;
+
+/// The application's public key from the developer portal, which discord signs interactions with.
+#[derive(Clone, Copy, Debug)]
+pub struct PublicKey(VerifyingKey);
+
+impl FromStr for PublicKey {
+    type Err = SignatureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut key = [0; 32];
+        hex::decode_to_slice(s.trim(), &mut key)?;
+        Ok(Self(VerifyingKey::from_bytes(&key)?))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SignatureError {
+    #[error("Missing {0} header")]
+    MissingHeader(&'static str),
+    #[error("Header is not valid hex: {0}")]
+    Hex(#[from] hex::FromHexError),
+    #[error("Signature is invalid: {0}")]
+    Signature(#[from] ed25519_dalek::SignatureError),
+}
+
+/// Check that discord sent `body` at `timestamp`, using the hex encoded `signature`
+/// from its `X-Signature-Ed25519` header.
+/// # Errors
+/// If the signature isn't valid hex, or doesn't match
+pub fn verify(
+    key: &PublicKey,
+    signature: &str,
+    timestamp: &str,
+    body: &[u8],
+) -> Result<(), SignatureError> {
+    let mut signature_bytes = [0; Signature::BYTE_SIZE];
+    hex::decode_to_slice(signature, &mut signature_bytes)?;
+    let signature = Signature::from_bytes(&signature_bytes);
+    let message = [timestamp.as_bytes(), body].concat();
+    key.0.verify_strict(&message, &signature)?;
+    Ok(())
+}
+
+#[derive(Clone)]
+struct EndpointState {
+    slash: XpdSlash,
+    key: PublicKey,
+}
+
+impl XpdSlash {
+    /// Serve the interactions endpoint on `addr` until `shutdown` is cancelled.
+    /// Once its URL is set in the developer portal, discord stops sending interactions
+    /// over the gateway, so any number of processes can share them behind a load balancer.
+    /// # Errors
+    /// If we can't listen on `addr`
+    pub async fn serve_interactions(
+        self,
+        addr: SocketAddr,
+        key: PublicKey,
+        shutdown: CancellationToken,
+    ) -> std::io::Result<()> {
+        let state = EndpointState { slash: self, key };
+        let router = Router::new()
+            .route("/interactions", post(interaction))
+            .with_state(state);
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        info!(?addr, "Serving interactions endpoint");
+        axum::serve(listener, router)
+            .with_graceful_shutdown(shutdown.cancelled_owned())
+            .await
+    }
+}
+
+async fn interaction(
+    State(state): State<EndpointState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Err(source) = verify_headers(&state.key, &headers, &body) {
+        debug!(?source, "Rejected interaction with bad signature");
+        return (StatusCode::UNAUTHORIZED, source.to_string()).into_response();
+    }
+    let interaction: Interaction = match serde_json::from_slice(&body) {
+        Ok(interaction) => interaction,
+        Err(source) => {
+            warn!(?source, "Could not deserialize interaction");
+            return (StatusCode::BAD_REQUEST, source.to_string()).into_response();
+        }
+    };
+    // discord pings the endpoint when it's set up, and now and then after
+    if interaction.kind == InteractionType::Ping {
+        return Json(InteractionResponse {
+            kind: InteractionResponseType::Pong,
+            data: None,
+        })
+        .into_response();
+    }
+    let token = interaction.token.clone();
+    let (response, followup) = split_attachments(state.slash.run(interaction).await);
+    if let Some(followup) = followup {
+        let slash_state = state.slash.state.clone();
+        state.slash.state.spawn(async move {
+            slash_state.send_followup(followup.into(), &token).await;
+        });
+    }
+    Json(response).into_response()
+}
+
+/// Attachment bytes are skipped when responses are serialized, so a response with attachments
+/// is deferred instead, and its data is returned to be sent as a followup.
+fn split_attachments(
+    response: InteractionResponse,
+) -> (InteractionResponse, Option<InteractionResponseData>) {
+    let has_attachments = response
+        .data
+        .as_ref()
+        .and_then(|data| data.attachments.as_ref())
+        .is_some_and(|attachments| !attachments.is_empty());
+    if !has_attachments {
+        return (response, None);
+    }
+    let kind = if response.kind == InteractionResponseType::UpdateMessage {
+        InteractionResponseType::DeferredUpdateMessage
+    } else {
+        InteractionResponseType::DeferredChannelMessageWithSource
+    };
+    let deferred = InteractionResponse {
+        kind,
+        // keeps the followup ephemeral, if it should be
+        data: Some(InteractionResponseData {
+            flags: response.data.as_ref().and_then(|data| data.flags),
+            ..InteractionResponseData::default()
+        }),
+    };
+    (deferred, response.data)
+}
+
+fn verify_headers(key: &PublicKey, headers: &HeaderMap, body: &[u8]) -> Result<(), SignatureError> {
+    let header = |name: &'static str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .ok_or(SignatureError::MissingHeader(name))
+    };
+    verify(
+        key,
+        header("X-Signature-Ed25519")?,
+        header("X-Signature-Timestamp")?,
+        body,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signer, SigningKey};
+    use rand::rngs::OsRng;
+    use twilight_model::{channel::message::MessageFlags, http::attachment::Attachment};
+
+    use super::*;
+    use crate::XpdSlashResponse;
+
+    const TIMESTAMP: &str = "1729209600";
+    const BODY: &[u8] = br#"{"type":1}"#;
+
+    fn sign(key: &SigningKey, timestamp: &str, body: &[u8]) -> String {
+        let message = [timestamp.as_bytes(), body].concat();
+        hex::encode(key.sign(&message).to_bytes())
+    }
+
+    fn public(key: &SigningKey) -> PublicKey {
+        hex::encode(key.verifying_key().as_bytes()).parse().unwrap()
+    }
+
+    #[test]
+    fn accepts_signed_interactions() {
+        let key = SigningKey::generate(&mut OsRng);
+        let signature = sign(&key, TIMESTAMP, BODY);
+        verify(&public(&key), &signature, TIMESTAMP, BODY).unwrap();
+    }
+
+    #[test]
+    fn rejects_tampered_interactions() {
+        let key = SigningKey::generate(&mut OsRng);
+        let public_key = public(&key);
+        let signature = sign(&key, TIMESTAMP, BODY);
+        assert!(verify(&public_key, &signature, TIMESTAMP, br#"{"type":2}"#).is_err());
+        assert!(verify(&public_key, &signature, "1729209601", BODY).is_err());
+        assert!(verify(&public_key, "not hex", TIMESTAMP, BODY).is_err());
+        assert!(verify(&public_key, &signature[2..], TIMESTAMP, BODY).is_err());
+
+        let impostor = SigningKey::generate(&mut OsRng);
+        let forged = sign(&impostor, TIMESTAMP, BODY);
+        assert!(verify(&public_key, &forged, TIMESTAMP, BODY).is_err());
+    }
+
+    #[test]
+    fn checks_headers() {
+        let key = SigningKey::generate(&mut OsRng);
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Signature-Timestamp", TIMESTAMP.parse().unwrap());
+        assert!(matches!(
+            verify_headers(&public(&key), &headers, BODY),
+            Err(SignatureError::MissingHeader("X-Signature-Ed25519"))
+        ));
+        headers.insert(
+            "X-Signature-Ed25519",
+            sign(&key, TIMESTAMP, BODY).parse().unwrap(),
+        );
+        verify_headers(&public(&key), &headers, BODY).unwrap();
+    }
+
+    #[test]
+    fn attachments_are_sent_in_followups() {
+        let file = b"[{\"id\":1,\"xp\":100}]".to_vec();
+        let response: InteractionResponse = XpdSlashResponse::new()
+            .attachments([Attachment::from_bytes(
+                "export.json".to_string(),
+                file.clone(),
+                0,
+            )])
+            .ephemeral(true)
+            .into();
+        let (deferred, followup) = split_attachments(response);
+        assert_eq!(
+            deferred.kind,
+            InteractionResponseType::DeferredChannelMessageWithSource
+        );
+        assert_eq!(
+            deferred.data.and_then(|data| data.flags),
+            Some(MessageFlags::EPHEMERAL)
+        );
+        let attachments = followup.and_then(|data| data.attachments).unwrap();
+        assert_eq!(attachments[0].file, file);
+
+        let response: InteractionResponse = XpdSlashResponse::with_embed_text("hi").into();
+        let (same, followup) = split_attachments(response.clone());
+        assert_eq!(same, response);
+        assert!(followup.is_none());
+    }
+
+    #[test]
+    fn parses_public_keys() {
+        assert!("abcd".parse::<PublicKey>().is_err());
+        assert!("zz".repeat(32).parse::<PublicKey>().is_err());
+    }
+}
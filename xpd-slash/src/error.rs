@@ -1,3 +1,5 @@
+use twilight_model::id::{marker::RoleMarker, Id};
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Interaction parser encountered an error!")]
@@ -5,7 +7,7 @@ pub enum Error {
     #[error("Processing task panicked!")]
     TaskPanicked(#[from] tokio::task::JoinError),
     #[error("Discord error!")]
-    TwilightHttp(#[from] twilight_http::Error),
+    TwilightHttp(Box<twilight_http::Error>),
     #[error("HTTP error!")]
     ReqwestHttp(#[from] reqwest::Error),
     #[error("Invalid message attachment!")]
@@ -32,10 +34,17 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error("Could not build template: {0}")]
     SimpleInterpolation(#[from] simpleinterpolation::Error),
+    #[error("Could not build template: {source}\n```\n{underline}\n```")]
+    InvalidTemplate {
+        source: simpleinterpolation::Error,
+        underline: String,
+    },
     #[error("Discord API decoding error")]
     DiscordApiDeserialization(#[from] twilight_http::response::DeserializeBodyError),
     #[error("Invalid guild config: {0}")]
     InvalidGuildConfig(#[from] crate::config::GuildConfigErrorReport),
+    #[error("Could not load guild config: {0}")]
+    LoadGuildConfig(#[from] xpd_common::GuildConfigError),
     #[error("Discord sent a command that is not known!")]
     UnrecognizedCommand,
     #[error("Discord did not send a user object for the command invoker when it was required!")]
@@ -81,8 +90,30 @@ pub enum Error {
     XpWouldBeNegative,
     #[error("Unknown variable `{0}` used in level-up message!")]
     UnknownInterpolationVariable(String),
+    #[error("Unknown filter `{0}` used in level-up message!")]
+    UnknownInterpolationFilter(String),
     #[error("Level up message must be less than 512 characters!")]
     LevelUpMessageTooLong,
+    #[error("Level up embed title must be less than 256 characters!")]
+    LevelUpEmbedTitleTooLong,
+    #[error("This server already has the maximum of {0} extra level-up messages!")]
+    TooManyLevelUpMessages(i64),
+    #[error("That level-up message does not exist!")]
+    UnknownLevelUpMessage,
+    #[error("Linear and exponential level curves need an XP amount!")]
+    LevelCurveMissingXp,
+    #[error("Exponential level curves need a growth above 1!")]
+    InvalidLevelCurveGrowth,
+    #[error("Level curve tables must be a list of increasing XP amounts, like `100, 250, 500`!")]
+    InvalidLevelCurveTable,
+    #[error("The XP wall can't have a start or slope while it's turned off!")]
+    LevelCurveWallDisabled,
+    #[error("Prestige isn't turned on in this server!")]
+    PrestigeDisabled,
+    #[error("You need to reach level {0} before you can prestige!")]
+    NotReadyToPrestige(i64),
+    #[error("Level {0} can't be reached with this server's level curve, so nobody can prestige!")]
+    PrestigeLevelUnreachable(i64),
     #[error("Level up channel must be a text channel!")]
     LevelUpChannelMustBeText,
     #[error("That card does not exist!")]
@@ -99,4 +130,18 @@ pub enum Error {
     NoInteractionInvocationOnInteractionMessage,
     #[error("You didn't create this leaderboard.")]
     NotYourLeaderboard,
+    #[error("<@&{0}> is already a level reward, so it can't be another kind of reward too!")]
+    AlreadyLevelReward(Id<RoleMarker>),
+    #[error("<@&{0}> is already a rank reward, so it can't be another kind of reward too!")]
+    AlreadyRankReward(Id<RoleMarker>),
+    #[error("<@&{0}> is already a prestige reward, so it can't be another kind of reward too!")]
+    AlreadyPrestigeReward(Id<RoleMarker>),
+    #[error("A reward can't give and take away the same role!")]
+    RewardGivesAndTakesRole,
+}
+
+impl From<twilight_http::Error> for Error {
+    fn from(source: twilight_http::Error) -> Self {
+        Self::TwilightHttp(Box::new(source))
+    }
 }
@@ -57,7 +57,7 @@ async fn download(
 ) -> Result<XpdSlashResponse, Error> {
     let invoker = Arc::new(invoker);
     let levels = query!(
-        "SELECT guild, xp FROM levels WHERE id = $1",
+        "SELECT guild, xp, prestige FROM levels WHERE id = $1",
         id_to_db(invoker.id)
     )
     .fetch_all(&state.db);
@@ -69,7 +69,7 @@ async fn download(
 
     let levels: Vec<UserXpArchiveEntry> = levels
         .into_iter()
-        .map(|v| UserXpArchiveEntry::from_record(v.guild, v.xp))
+        .map(|v| UserXpArchiveEntry::from_record(v.guild, v.xp, v.prestige))
         .collect();
 
     let levels = multicsv(&levels)?;
@@ -103,13 +103,15 @@ async fn download(
 struct UserXpArchiveEntry {
     guild: Id<GuildMarker>,
     xp: i64,
+    prestige: i64,
 }
 
 impl UserXpArchiveEntry {
-    fn from_record(guild: i64, xp: i64) -> Self {
+    fn from_record(guild: i64, xp: i64, prestige: i64) -> Self {
         Self {
             guild: db_to_id(guild),
             xp,
+            prestige,
         }
     }
 }
@@ -40,7 +40,7 @@ pub async fn leaderboard(
         0
     };
     Ok(InteractionResponse {
-        data: Some(gen_leaderboard(guild_id, state.db, zpage, guild_command.show_off).await?),
+        data: Some(gen_leaderboard(guild_id, &state, zpage, guild_command.show_off).await?),
         kind: InteractionResponseType::ChannelMessageWithSource,
     })
 }
@@ -51,7 +51,7 @@ const USERS_PER_PAGE: i64 = USERS_PER_PAGE_USIZE as i64;
 
 async fn gen_leaderboard(
     guild_id: Id<GuildMarker>,
-    db: sqlx::PgPool,
+    state: &SlashState,
     zpage: i64,
     show_off: Option<bool>,
 ) -> Result<InteractionResponseData, Error> {
@@ -61,16 +61,17 @@ async fn gen_leaderboard(
     let is_ephemeral = !show_off.is_some_and(|v| v);
 
     let users = query!(
-        "SELECT * FROM levels WHERE guild = $1 ORDER BY xp DESC LIMIT $2 OFFSET $3",
+        "SELECT * FROM levels WHERE guild = $1 ORDER BY prestige DESC, xp DESC LIMIT $2 OFFSET $3",
         id_to_db(guild_id),
         USERS_PER_PAGE + 1,
         zpage * USERS_PER_PAGE
     )
-    .fetch_all(&db)
+    .fetch_all(&state.db)
     .await?;
     if users.is_empty() {
         return Err(Error::NoUsersForPage);
     }
+    let curve = state.get_level_curve(guild_id).await?;
     let one_more_page_bro = users.len() >= (USERS_PER_PAGE_USIZE + 1);
     let last_user_idx = users.len().clamp(0, USERS_PER_PAGE_USIZE);
     let users = &users[0..last_user_idx];
@@ -79,13 +80,17 @@ async fn gen_leaderboard(
     let mut description = String::with_capacity(users.len() * 128);
     for (i, user) in users.iter().enumerate() {
         let xp = user.xp.try_into().unwrap_or(0);
-        let level = mee6::LevelInfo::new(xp).level();
+        let level = mee6::LevelInfo::new(&curve, xp).level();
         let rank: i64 = i
             .try_into()
             .map_or(-1, |v: i64| v + (zpage * USERS_PER_PAGE) + 1);
+        let prestige = match user.prestige {
+            0 => String::new(),
+            tier => format!("Prestige {tier}, "),
+        };
         writeln!(
             description,
-            "{rank}. <@{}> \u{2013} Level {level} ({xp} xp)",
+            "{rank}. <@{}> \u{2013} {prestige}Level {level} ({xp} xp)",
             user.id
         )
         .ok();
@@ -169,7 +174,7 @@ pub async fn process_modal_submit(
     let zpage = choice - 1;
     Ok(InteractionResponse {
         kind: InteractionResponseType::UpdateMessage,
-        data: Some(gen_leaderboard(guild_id, state.db, zpage, Some(true)).await?),
+        data: Some(gen_leaderboard(guild_id, &state, zpage, Some(true)).await?),
     })
 }
 
@@ -236,7 +241,7 @@ pub async fn process_message_component(
             let offset: i64 = offset_str.parse()?;
             Ok(InteractionResponse {
                 kind: InteractionResponseType::UpdateMessage,
-                data: Some(gen_leaderboard(guild_id, state.db, offset, Some(true)).await?),
+                data: Some(gen_leaderboard(guild_id, &state, offset, Some(true)).await?),
             })
         }
     }
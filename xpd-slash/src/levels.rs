@@ -10,10 +10,17 @@ use twilight_model::{
     util::ImageHash,
 };
 use twilight_util::builder::embed::EmbedBuilder;
-use xpd_common::{id_to_db, DisplayName, MemberDisplayInfo};
+use xpd_common::{id_to_db, DisplayName, GuildConfig, MemberDisplayInfo};
 use xpd_rank_card::customizations::{Color, Customizations};
 
-use crate::{Error, SlashState, XpdSlashResponse};
+use crate::{Error, SlashState, UserStats, XpdSlashResponse};
+
+/// Whether a member at this level could run `/prestige` right now
+pub fn can_prestige(config: &GuildConfig, level_info: &mee6::LevelInfo) -> bool {
+    config
+        .prestige_level()
+        .is_some_and(|level| i64::try_from(level_info.level()).unwrap_or(i64::MAX) >= level)
+}
 
 pub async fn get_level(
     guild_id: Id<GuildMarker>,
@@ -29,7 +36,12 @@ pub async fn get_level(
         MessageFlags::EPHEMERAL
     };
 
-    let level_info = mee6::LevelInfo::new(u64::try_from(rank_stats.xp).unwrap_or(0));
+    let config = state.get_guild_config(guild_id).await?;
+    let level_info = mee6::LevelInfo::new(
+        &*config.level_curve(),
+        u64::try_from(rank_stats.xp).unwrap_or(0),
+    );
+    let can_prestige = can_prestige(&config, &level_info);
     let content = if target.bot {
         "Bots aren't ranked, that would be silly!".to_string()
     } else if invoker == target.id {
@@ -41,7 +53,8 @@ pub async fn get_level(
                 target,
                 guild_id,
                 level_info,
-                rank_stats.rank,
+                rank_stats,
+                can_prestige,
                 flags,
             )
             .await;
@@ -57,7 +70,8 @@ pub async fn get_level(
             target,
             guild_id,
             level_info,
-            rank_stats.rank,
+            rank_stats,
+            can_prestige,
             flags,
         )
         .await;
@@ -71,10 +85,19 @@ async fn generate_level_response(
     user: MemberDisplayInfo,
     guild_id: Id<GuildMarker>,
     level_info: mee6::LevelInfo,
-    rank: i64,
+    user_stats: UserStats,
+    can_prestige: bool,
     flags: MessageFlags,
 ) -> Result<XpdSlashResponse, Error> {
-    let card = gen_card(state.clone(), user, Some(guild_id), level_info, rank).await?;
+    let card = gen_card(
+        state.clone(),
+        user,
+        Some(guild_id),
+        level_info,
+        user_stats,
+        can_prestige,
+    )
+    .await?;
     Ok(XpdSlashResponse::new().attachments([card]).flags(flags))
 }
 
@@ -95,7 +118,8 @@ pub async fn gen_card(
     user: MemberDisplayInfo,
     guild_id: Option<Id<GuildMarker>>,
     level_info: mee6::LevelInfo,
-    rank: i64,
+    user_stats: UserStats,
+    can_prestige: bool,
 ) -> Result<Attachment, Error> {
     let customizations_future = get_customizations_fields(state.clone(), user.id, guild_id);
     let avatar_future = get_avatar(state.clone(), user.id, user.avatar);
@@ -106,21 +130,30 @@ pub async fn gen_card(
         .svg
         .render(xpd_rank_card::Context {
             level: level_info.level(),
-            rank,
+            rank: user_stats.rank,
+            prestige: u64::try_from(user_stats.prestige).unwrap_or(0),
+            can_prestige,
             name: user.display_name().to_string(),
             percentage,
             current: level_info.xp(),
-            needed: mee6::xp_needed_for_level(level_info.level() + 1),
+            needed: level_info
+                .next_level_xp()
+                .unwrap_or_else(|| level_info.xp()),
             customizations,
             avatar,
         })
         .await?;
     Ok(Attachment {
         description: Some(format!(
-            "{} is level {} (rank #{}), and is {}% of the way to level {}.",
+            "{}{} is level {} (rank #{}), and is {}% of the way to level {}.",
             user.display_name(),
+            if user_stats.prestige > 0 {
+                format!(" (prestige {})", user_stats.prestige)
+            } else {
+                String::new()
+            },
             level_info.level(),
-            rank,
+            user_stats.rank,
             (level_info.percentage() * 100.0).round(),
             level_info.level() + 1
         )),
@@ -150,25 +183,25 @@ pub async fn get_customizations(
     };
     let defaults = Customizations::default_customizations_str(&customizations.card_layout);
     Ok(Customizations {
-        username: color_or_default(&customizations.username, defaults.username)?,
-        rank: color_or_default(&customizations.rank, defaults.rank)?,
-        level: color_or_default(&customizations.level, defaults.level)?,
-        border: color_or_default(&customizations.border, defaults.border)?,
-        background: color_or_default(&customizations.background, defaults.background)?,
+        username: color_or_default(customizations.username.as_deref(), defaults.username)?,
+        rank: color_or_default(customizations.rank.as_deref(), defaults.rank)?,
+        level: color_or_default(customizations.level.as_deref(), defaults.level)?,
+        border: color_or_default(customizations.border.as_deref(), defaults.border)?,
+        background: color_or_default(customizations.background.as_deref(), defaults.background)?,
         progress_foreground: color_or_default(
-            &customizations.progress_foreground,
+            customizations.progress_foreground.as_deref(),
             defaults.progress_foreground,
         )?,
         progress_background: color_or_default(
-            &customizations.progress_background,
+            customizations.progress_background.as_deref(),
             defaults.progress_background,
         )?,
         background_xp_count: color_or_default(
-            &customizations.background_xp_count,
+            customizations.background_xp_count.as_deref(),
             defaults.background_xp_count,
         )?,
         foreground_xp_count: color_or_default(
-            &customizations.foreground_xp_count,
+            customizations.foreground_xp_count.as_deref(),
             defaults.foreground_xp_count,
         )?,
         font: customizations.font.unwrap_or(defaults.font),
@@ -177,9 +210,9 @@ pub async fn get_customizations(
     })
 }
 
-fn color_or_default(color: &Option<String>, default: Color) -> Result<Color, Error> {
-    if let Some(color) = &color {
-        Ok(Color::from_hex(color)?)
+fn color_or_default(color: Option<&str>, default: Color) -> Result<Color, Error> {
+    if let Some(color) = color {
+        Ok(Color::from_hex(&color)?)
     } else {
         Ok(default)
     }
@@ -197,7 +230,7 @@ async fn get_avatar(
                 (user_id.get() >> 22) % 6
             )
         },
-        |hash| format!("https://cdn.discordapp.com/avatars/{user_id}/{hash}.png",),
+        |hash| format!("https://cdn.discordapp.com/avatars/{user_id}/{hash}.png"),
     );
     debug!(url, "Downloading avatar");
     let png = state.http.get(url).send().await?.bytes().await?;
@@ -6,6 +6,7 @@ mod autocomplete;
 mod cmd_defs;
 mod config;
 mod dispatch;
+mod endpoint;
 mod error;
 mod gdpr;
 mod help;
@@ -13,10 +14,13 @@ mod leaderboard;
 mod levels;
 mod manage_card;
 mod manager;
+mod prestige;
 mod response;
+mod rewards;
 
 use std::{future::Future, sync::Arc, time::Instant};
 
+pub use endpoint::{PublicKey, SignatureError};
 pub use error::Error;
 pub use response::XpdSlashResponse;
 use sqlx::PgPool;
@@ -34,7 +38,9 @@ use twilight_model::{
     },
 };
 use twilight_util::builder::InteractionResponseDataBuilder;
-use xpd_common::{id_to_db, GuildConfig, RequiredEvents};
+use xpd_common::{
+    id_to_db, query_guild_config, CacheInvalidator, CacheKind, GuildConfig, RequiredEvents,
+};
 use xpd_rank_card::SvgState;
 
 #[macro_use]
@@ -50,17 +56,28 @@ pub struct XpdSlash {
     state: SlashState,
 }
 
-pub struct InvalidateCache(pub Id<GuildMarker>);
-
+/// Hands cache updates to the listener running in this process.
 #[derive(Clone)]
 pub struct UpdateChannels {
     pub config: UpdateSender<GuildConfig>,
-    pub rewards: Sender<InvalidateCache>,
+    pub invalidate: UpdateSender<CacheKind>,
+}
+
+impl CacheInvalidator for UpdateChannels {
+    async fn invalidate(&self, kind: CacheKind, guild: Id<GuildMarker>) {
+        let _ = self.invalidate.send((guild, kind)).await;
+    }
+
+    async fn update_config(&self, guild: Id<GuildMarker>, config: GuildConfig) {
+        let _ = self.config.send((guild, config)).await;
+    }
 }
 
 impl XpdSlash {
     /// Creates a new xpd slash, which can be passed around
     /// Make sure to trim your ``root_url`` trailing slash.
+    /// Commands are only registered with discord if `register_commands` is set, so
+    /// processes sharing a bot don't all overwrite them.
     ///
     /// # Panics
     /// If loading resources or connecting to a database fails, this function will panic.
@@ -74,6 +91,7 @@ impl XpdSlash {
         control_guild: Id<GuildMarker>,
         owners: Vec<Id<UserMarker>>,
         update_channels: UpdateChannels,
+        register_commands: bool,
     ) -> Self {
         let svg = SvgState::new("xpd-card-resources").unwrap();
         let rt = Handle::current();
@@ -89,8 +107,10 @@ impl XpdSlash {
             owners: owners.into(),
             update_channels,
         };
-        info!("Creating commands...");
-        state.register_slashes().await;
+        if register_commands {
+            info!("Creating commands...");
+            state.register_slashes().await;
+        }
         Self { state }
     }
 
@@ -108,7 +128,7 @@ impl XpdSlash {
             .await
         {
             error!(?error, "Failed to ack discord gateway message");
-        };
+        }
     }
 
     async fn run(&self, interaction: Interaction) -> InteractionResponse {
@@ -167,14 +187,30 @@ pub struct SlashState {
 
 impl SlashState {
     pub async fn update_config(&self, guild: Id<GuildMarker>, config: GuildConfig) {
-        let _ = self.update_channels.config.send((guild, config)).await;
+        self.update_channels.update_config(guild, config).await;
     }
 
     pub async fn invalidate_rewards(&self, guild: Id<GuildMarker>) {
-        let _ = self
-            .update_channels
-            .rewards
-            .send(InvalidateCache(guild))
+        self.update_channels
+            .invalidate(CacheKind::Rewards, guild)
+            .await;
+    }
+
+    pub async fn invalidate_channel_rules(&self, guild: Id<GuildMarker>) {
+        self.update_channels
+            .invalidate(CacheKind::ChannelRules, guild)
+            .await;
+    }
+
+    pub async fn invalidate_role_rules(&self, guild: Id<GuildMarker>) {
+        self.update_channels
+            .invalidate(CacheKind::RoleRules, guild)
+            .await;
+    }
+
+    pub async fn invalidate_level_up_messages(&self, guild: Id<GuildMarker>) {
+        self.update_channels
+            .invalidate(CacheKind::LevelUpMessages, guild)
             .await;
     }
 }
@@ -183,9 +219,38 @@ impl SlashState {
 pub struct UserStats {
     xp: i64,
     rank: i64,
+    prestige: i64,
 }
 
 impl SlashState {
+    /// Load a guild's config straight from the database, or the default if it has none.
+    /// # Errors
+    /// This function errors if the database can't be reached, or the stored config is invalid.
+    /// # Panics
+    /// This can panic if sqlx is unable to convert the rows to the proper types.
+    pub async fn get_guild_config(&self, guild_id: Id<GuildMarker>) -> Result<GuildConfig, Error> {
+        let config = query_guild_config!(
+            "SELECT ",
+            " FROM guild_configs WHERE id = $1",
+            id_to_db(guild_id),
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .map_or_else(|| Ok(GuildConfig::default()), TryInto::try_into)?;
+        Ok(config)
+    }
+
+    /// The level curve a guild has chosen, or the MEE6 curve if it hasn't.
+    /// # Errors
+    /// This function errors when [`Self::get_guild_config`] does.
+    pub async fn get_level_curve(&self, guild_id: Id<GuildMarker>) -> Result<mee6::Curve, Error> {
+        Ok(self
+            .get_guild_config(guild_id)
+            .await?
+            .level_curve
+            .unwrap_or_default())
+    }
+
     /// Get public-facing statistics for a user
     /// # Errors
     /// This function can error when sqlx fails to get the right datatype.
@@ -197,16 +262,18 @@ impl SlashState {
         guild_id: Id<GuildMarker>,
     ) -> Result<UserStats, Error> {
         // Select current XP from the database, return 0 if there is no row
-        let xp = query!(
-            "SELECT xp FROM levels WHERE id = $1 AND guild = $2",
+        let (xp, prestige) = query!(
+            "SELECT xp, prestige FROM levels WHERE id = $1 AND guild = $2",
             id_to_db(id),
             id_to_db(guild_id)
         )
         .fetch_optional(&self.db)
         .await?
-        .map_or(0, |v| v.xp);
+        .map_or((0, 0), |v| (v.xp, v.prestige));
+        // Members who prestiged more often rank above everyone below their tier
         let rank = query!(
-            "SELECT COUNT(*) as count FROM levels WHERE xp > $1 AND guild = $2",
+            "SELECT COUNT(*) as count FROM levels WHERE (prestige, xp) > ($1, $2) AND guild = $3",
+            prestige,
             xp,
             id_to_db(guild_id)
         )
@@ -215,7 +282,7 @@ impl SlashState {
         .count
         .unwrap_or(0)
             + 1;
-        Ok(UserStats { xp, rank })
+        Ok(UserStats { xp, rank, prestige })
     }
 
     /// # Errors
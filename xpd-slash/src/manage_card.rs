@@ -4,7 +4,7 @@ use twilight_model::id::{
     Id,
 };
 use twilight_util::builder::embed::{EmbedBuilder, ImageSource};
-use xpd_common::{id_to_db, MemberDisplayInfo};
+use xpd_common::{id_to_db, GuildConfig, MemberDisplayInfo};
 use xpd_rank_card::ConfigItem;
 
 use crate::{
@@ -36,16 +36,34 @@ pub async fn user_card_update(
         }
         CardCommand::Edit(edit) => (process_edit(edit, state, invoker.id.cast()).await?, invoker),
     };
-    let user_stats = if let Some(id) = guild_id {
-        state.get_user_stats(target.id, id).await?
+    let (user_stats, config) = if let Some(id) = guild_id {
+        (
+            state.get_user_stats(target.id, id).await?,
+            state.get_guild_config(id).await?,
+        )
     } else {
         // I am so mature.
-        UserStats { xp: 420, rank: 69 }
+        let user_stats = UserStats {
+            xp: 420,
+            rank: 69,
+            prestige: 0,
+        };
+        (user_stats, GuildConfig::default())
     };
-    let level_info = LevelInfo::new(u64::try_from(user_stats.xp).unwrap_or(0));
-    let card =
-        crate::levels::gen_card(state.clone(), target, guild_id, level_info, user_stats.rank)
-            .await?;
+    let level_info = LevelInfo::new(
+        &*config.level_curve(),
+        u64::try_from(user_stats.xp).unwrap_or(0),
+    );
+    let can_prestige = crate::levels::can_prestige(&config, &level_info);
+    let card = crate::levels::gen_card(
+        state.clone(),
+        target,
+        guild_id,
+        level_info,
+        user_stats,
+        can_prestige,
+    )
+    .await?;
     let embed = EmbedBuilder::new()
         .description(contents)
         .image(ImageSource::attachment("card.png")?)
@@ -67,13 +85,20 @@ pub async fn guild_card_update(
         GuildCardCommand::Edit(edit) => process_edit(edit, state, guild_id.cast()).await?,
     };
     let referenced_user = fake_user(guild_id.cast());
-    let level_info = LevelInfo::new(40);
+    let curve = state.get_level_curve(guild_id).await?;
+    let level_info = LevelInfo::new(&curve, 40);
+    let user_stats = UserStats {
+        xp: 40,
+        rank: 127,
+        prestige: 0,
+    };
     let card = crate::levels::gen_card(
         state.clone(),
         referenced_user,
         Some(guild_id),
         level_info,
-        127,
+        user_stats,
+        false,
     )
     .await?;
     let embed = EmbedBuilder::new()
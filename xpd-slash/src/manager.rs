@@ -8,7 +8,7 @@ use twilight_model::{
     channel::{message::AllowedMentions, Attachment},
     http::attachment::Attachment as HttpAttachment,
     id::{
-        marker::{GuildMarker, UserMarker},
+        marker::{ChannelMarker, GuildMarker, MessageMarker, RoleMarker, UserMarker},
         Id,
     },
 };
@@ -18,11 +18,14 @@ use xpd_common::{db_to_id, id_to_db};
 use crate::{
     cmd_defs::{
         manage::{
-            XpCommandExperience, XpCommandRewards, XpCommandRewardsAdd, XpCommandRewardsRemove,
+            XpCommandExperience, XpCommandMultipliers, XpCommandMultipliersAdd,
+            XpCommandMultipliersRemove, XpCommandRewards, XpCommandRewardsAdd,
+            XpCommandRewardsAddPrestige, XpCommandRewardsAddRank, XpCommandRewardsRemove,
         },
         XpCommand,
     },
     dispatch::Respondable,
+    rewards::{list_member_roles, sync_after_edit, GuildRewards, RoleSyncOutcome},
     Error, SlashState, XpdSlashResponse,
 };
 
@@ -33,7 +36,10 @@ pub async fn process_xp(
     state: SlashState,
 ) -> Result<XpdSlashResponse, Error> {
     let contents = match data {
-        XpCommand::Rewards(rewards) => process_rewards(rewards, guild_id, state).await,
+        XpCommand::Rewards(rewards) => process_rewards(rewards, respondable, guild_id, state).await,
+        XpCommand::Multipliers(multipliers) => {
+            process_multipliers(multipliers, guild_id, state).await
+        }
         XpCommand::Experience(experience) => {
             process_experience(experience, respondable, guild_id, state).await
         }
@@ -68,7 +74,7 @@ async fn process_experience(
         XpCommandExperience::Reset(rst) => reset_user_xp(guild_id, rst.user, state).await,
         XpCommandExperience::Set(st) => set_user_xp(guild_id, st.user, st.xp, state).await,
         XpCommandExperience::ResetGuild(rst) => {
-            reset_guild_xp(guild_id, rst.confirm_message, state).await
+            reset_guild_xp(guild_id, rst.confirm_message, respondable, state).await
         }
     }
 }
@@ -80,30 +86,32 @@ async fn modify_user_xp(
     state: SlashState,
 ) -> Result<String, Error> {
     let mut txn = state.db.begin().await?;
-    let xp = query!(
+    let record = query!(
         "INSERT INTO levels (id, guild, xp) VALUES ($1, $2, $3) \
-         ON CONFLICT (id, guild) DO UPDATE SET xp = excluded.xp + $3 \
-         RETURNING xp",
+         ON CONFLICT (id, guild) DO UPDATE SET xp = levels.xp + $3 \
+         RETURNING xp, prestige",
         id_to_db(user_id),
         id_to_db(guild_id),
         amount
     )
     .fetch_one(txn.as_mut())
-    .await?
-    .xp;
+    .await?;
+    let xp = record.xp;
     if xp.is_negative() {
         txn.rollback().await?;
         return Err(Error::XpWouldBeNegative);
     }
     txn.commit().await?;
-    let current_level = mee6::LevelInfo::new(xp.try_into().unwrap_or(0)).level();
+    let curve = state.get_level_curve(guild_id).await?;
+    let current_level = mee6::LevelInfo::new(&curve, xp.try_into().unwrap_or(0)).level();
     let (action, targeter) = if amount.is_positive() {
         ("Added", "to")
     } else {
         ("Removed", "from")
     };
     let amount_abs = amount.abs();
-    Ok(format!("{action} {amount_abs} XP {targeter} <@{user_id}>, leaving them with {xp} XP at level {current_level}"))
+    let roles_note = sync_after_edit(&state, guild_id, user_id, xp, record.prestige).await;
+    Ok(format!("{action} {amount_abs} XP {targeter} <@{user_id}>, leaving them with {xp} XP at level {current_level}.{roles_note}"))
 }
 
 async fn reset_user_xp(
@@ -118,8 +126,9 @@ async fn reset_user_xp(
     )
     .execute(&state.db)
     .await?;
+    let roles_note = sync_after_edit(&state, guild_id, user_id, 0, 0).await;
     Ok(format!(
-        "Deleted <@{user_id}> from my database in this server!"
+        "Deleted <@{user_id}> from my database in this server!{roles_note}"
     ))
 }
 
@@ -129,17 +138,22 @@ async fn set_user_xp(
     setpoint: i64,
     state: SlashState,
 ) -> Result<String, Error> {
-    query!(
-        "INSERT INTO levels (id, guild, xp) VALUES ($1, $2, $3) ON CONFLICT (id, guild) DO UPDATE SET xp = $3",
+    let prestige = query!(
+        "INSERT INTO levels (id, guild, xp) VALUES ($1, $2, $3) \
+         ON CONFLICT (id, guild) DO UPDATE SET xp = $3 \
+         RETURNING prestige",
         id_to_db(user_id),
         id_to_db(guild_id),
         setpoint
     )
-    .execute(&state.db)
-    .await?;
-    let level = mee6::LevelInfo::new(setpoint.try_into().unwrap_or(0));
+    .fetch_one(&state.db)
+    .await?
+    .prestige;
+    let curve = state.get_level_curve(guild_id).await?;
+    let level = mee6::LevelInfo::new(&curve, setpoint.try_into().unwrap_or(0));
+    let roles_note = sync_after_edit(&state, guild_id, user_id, setpoint, prestige).await;
     Ok(format!(
-        "Set <@{user_id}>'s XP to {}, leaving them at level {}",
+        "Set <@{user_id}>'s XP to {}, leaving them at level {}.{roles_note}",
         level.xp(),
         level.level()
     ))
@@ -215,6 +229,7 @@ const MAX_IMPORT_SIZE: usize = 1024 * 1024 * 10;
 
 async fn background_data_import(
     state: &SlashState,
+    report: &mut SyncReport,
     guild_id: Id<GuildMarker>,
     attachment: Attachment,
     overwrite: bool,
@@ -266,9 +281,27 @@ async fn background_data_import(
 
     let seconds = start.elapsed().as_secs_f64();
     let users = data.len();
-    Ok(XpdSlashResponse::with_embed_text(format!(
-        "Imported XP data for {users} users in {seconds:.2} seconds!"
-    )))
+    let mut message = format!("Imported XP data for {users} users in {seconds:.2} seconds!");
+
+    // imported XP can cross reward thresholds just like earned XP
+    let rewards = GuildRewards::load(state, guild_id).await?;
+    if !rewards.is_empty() {
+        let ids: Vec<i64> = data.iter().map(|user| id_to_db(user.id)).collect();
+        let members = query!(
+            "SELECT id, xp, prestige FROM levels WHERE guild = $1 AND id = ANY($2)",
+            db_guild,
+            &ids
+        )
+        .fetch_all(&state.db)
+        .await?
+        .into_iter()
+        .map(|row| (db_to_id(row.id), row.xp, row.prestige))
+        .collect();
+        let summary = sync_members(state, report, guild_id, &rewards, members).await;
+        message.push('\n');
+        summary.describe(&mut message)?;
+    }
+    Ok(XpdSlashResponse::with_embed_text(message))
 }
 
 async fn background_data_operation_wrapper(
@@ -278,8 +311,9 @@ async fn background_data_operation_wrapper(
     attachment: Option<Attachment>,
     overwrite: bool,
 ) {
+    let mut report = SyncReport::new(respondable);
     let xsr = if let Some(attachment) = attachment {
-        background_data_import(&state, guild_id, attachment, overwrite)
+        background_data_import(&state, &mut report, guild_id, attachment, overwrite)
             .await
             .unwrap_or_else(|source| {
                 error!(?source, "Failed to import level data");
@@ -294,18 +328,24 @@ async fn background_data_operation_wrapper(
             })
     }
     .ephemeral(true);
-    state.send_followup(xsr, respondable.token()).await;
+    report.finish(&state, xsr).await;
 }
 
 async fn process_rewards(
     cmd: XpCommandRewards,
+    respondable: Respondable,
     guild_id: Id<GuildMarker>,
     state: SlashState,
 ) -> Result<String, Error> {
     match cmd {
         XpCommandRewards::Add(add) => process_rewards_add(add, state, guild_id).await,
+        XpCommandRewards::AddRank(add) => process_rewards_add_rank(add, state, guild_id).await,
+        XpCommandRewards::AddPrestige(add) => {
+            process_rewards_add_prestige(add, state, guild_id).await
+        }
         XpCommandRewards::Remove(remove) => process_rewards_rm(remove, state, guild_id).await,
         XpCommandRewards::List(_list) => process_rewards_list(state, guild_id).await,
+        XpCommandRewards::Sync(_sync) => process_rewards_sync(state, respondable, guild_id),
     }
 }
 
@@ -314,27 +354,163 @@ async fn process_rewards_add(
     state: SlashState,
     guild_id: Id<GuildMarker>,
 ) -> Result<String, Error> {
-    query!(
-        "INSERT INTO role_rewards (id, requirement, guild) VALUES ($1, $2, $3)",
+    let remove_role = options.remove_role.map(|role| role.id);
+    if remove_role == Some(options.role.id) {
+        return Err(Error::RewardGivesAndTakesRole);
+    }
+    for role in std::iter::once(options.role.id).chain(remove_role) {
+        let is_rank_reward = query!(
+            "SELECT id FROM rank_rewards WHERE id = $1 AND guild = $2",
+            id_to_db(role),
+            id_to_db(guild_id)
+        )
+        .fetch_optional(&state.db)
+        .await?
+        .is_some();
+        if is_rank_reward {
+            return Err(Error::AlreadyRankReward(role));
+        }
+        if is_prestige_reward(&state, guild_id, role).await? {
+            return Err(Error::AlreadyPrestigeReward(role));
+        }
+    }
+
+    let mut txn = state.db.begin().await?;
+    let rewards = std::iter::once((options.role.id, false)).chain(remove_role.map(|id| (id, true)));
+    for (role, remove) in rewards {
+        query!(
+            "INSERT INTO role_rewards (id, requirement, guild, remove) VALUES ($1, $2, $3, $4) \
+                ON CONFLICT (guild, id, remove) DO UPDATE SET requirement = excluded.requirement",
+            id_to_db(role),
+            options.level,
+            id_to_db(guild_id),
+            remove
+        )
+        .execute(txn.as_mut())
+        .await?;
+    }
+    txn.commit().await?;
+    state.invalidate_rewards(guild_id).await;
+
+    let mut message = format!(
+        "Added role reward <@&{}> at level {}",
+        options.role.id, options.level
+    );
+    if let Some(remove_role) = remove_role {
+        write!(message, ", which takes away <@&{remove_role}>")?;
+    }
+    message.push('!');
+    Ok(message)
+}
+
+async fn process_rewards_add_rank(
+    options: XpCommandRewardsAddRank,
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<String, Error> {
+    let is_level_reward = query!(
+        "SELECT id FROM role_rewards WHERE id = $1 AND guild = $2",
         id_to_db(options.role.id),
-        options.level,
         id_to_db(guild_id)
     )
+    .fetch_optional(&state.db)
+    .await?
+    .is_some();
+    if is_level_reward {
+        return Err(Error::AlreadyLevelReward(options.role.id));
+    }
+    if is_prestige_reward(&state, guild_id, options.role.id).await? {
+        return Err(Error::AlreadyPrestigeReward(options.role.id));
+    }
+    query!(
+        "INSERT INTO rank_rewards (id, guild, max_rank) VALUES ($1, $2, $3) \
+            ON CONFLICT (id, guild) DO UPDATE SET max_rank = excluded.max_rank",
+        id_to_db(options.role.id),
+        id_to_db(guild_id),
+        options.top
+    )
     .execute(&state.db)
     .await?;
-    state.invalidate_rewards(guild_id).await;
     Ok(format!(
-        "Added role reward <@&{}> at level {}!",
-        options.role.id, options.level
+        "Added rank reward <@&{}> for the top {} members! It will be handed out within a few minutes.",
+        options.role.id, options.top
     ))
 }
 
+async fn process_rewards_add_prestige(
+    options: XpCommandRewardsAddPrestige,
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<String, Error> {
+    let role = options.role.id;
+    let is_level_reward = query!(
+        "SELECT id FROM role_rewards WHERE id = $1 AND guild = $2",
+        id_to_db(role),
+        id_to_db(guild_id)
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .is_some();
+    if is_level_reward {
+        return Err(Error::AlreadyLevelReward(role));
+    }
+    let is_rank_reward = query!(
+        "SELECT id FROM rank_rewards WHERE id = $1 AND guild = $2",
+        id_to_db(role),
+        id_to_db(guild_id)
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .is_some();
+    if is_rank_reward {
+        return Err(Error::AlreadyRankReward(role));
+    }
+    query!(
+        "INSERT INTO prestige_rewards (id, guild, tier) VALUES ($1, $2, $3) \
+            ON CONFLICT (id, guild) DO UPDATE SET tier = excluded.tier",
+        id_to_db(role),
+        id_to_db(guild_id),
+        options.tier
+    )
+    .execute(&state.db)
+    .await?;
+    state.invalidate_rewards(guild_id).await;
+    let mut message = format!(
+        "Added prestige reward <@&{role}> at prestige {}!",
+        options.tier
+    );
+    if state
+        .get_guild_config(guild_id)
+        .await?
+        .prestige_level()
+        .is_none()
+    {
+        message.push_str(" Prestige is turned off in this server, so turn it on with `/config prestige` to hand it out.");
+    }
+    Ok(message)
+}
+
+async fn is_prestige_reward(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    role: Id<RoleMarker>,
+) -> Result<bool, Error> {
+    Ok(query!(
+        "SELECT id FROM prestige_rewards WHERE id = $1 AND guild = $2",
+        id_to_db(role),
+        id_to_db(guild_id)
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .is_some())
+}
+
 async fn process_rewards_rm(
     options: XpCommandRewardsRemove,
     state: SlashState,
     guild_id: Id<GuildMarker>,
 ) -> Result<String, Error> {
-    if let Some(role) = options.role {
+    let message = if let Some(role) = options.role {
         query!(
             "DELETE FROM role_rewards WHERE id = $1 AND guild = $2",
             id_to_db(role),
@@ -342,7 +518,27 @@ async fn process_rewards_rm(
         )
         .execute(&state.db)
         .await?;
-        return Ok(format!("Removed role reward <@&{role}>!"));
+        let removed_rank = query!(
+            "DELETE FROM rank_rewards WHERE id = $1 AND guild = $2",
+            id_to_db(role),
+            id_to_db(guild_id)
+        )
+        .execute(&state.db)
+        .await?
+        .rows_affected()
+            > 0;
+        query!(
+            "DELETE FROM prestige_rewards WHERE id = $1 AND guild = $2",
+            id_to_db(role),
+            id_to_db(guild_id)
+        )
+        .execute(&state.db)
+        .await?;
+        let mut message = format!("Removed role reward <@&{role}>!");
+        if removed_rank {
+            message.push_str(" Members who have it will lose it within 10 minutes.");
+        }
+        message
     } else if let Some(level) = options.level {
         query!(
             "DELETE FROM role_rewards WHERE requirement = $1 AND guild = $2",
@@ -351,12 +547,14 @@ async fn process_rewards_rm(
         )
         .execute(&state.db)
         .await?;
-        return Ok(format!("Removed role reward for level {level}!"));
+        format!("Removed role reward for level {level}!")
+    } else {
+        return Err(Error::WrongArgumentCount(
+            "`/xp rewards remove` requires either a level or a role!",
+        ));
     };
     state.invalidate_rewards(guild_id).await;
-    Err(Error::WrongArgumentCount(
-        "`/xp rewards remove` requires either a level or a role!",
-    ))
+    Ok(message)
 }
 
 async fn process_rewards_list(
@@ -364,20 +562,65 @@ async fn process_rewards_list(
     guild_id: Id<GuildMarker>,
 ) -> Result<String, Error> {
     let mut roles = query!(
-        "SELECT id, requirement FROM role_rewards WHERE guild = $1",
+        "SELECT id, requirement, remove FROM role_rewards WHERE guild = $1",
         id_to_db(guild_id)
     )
     .fetch_all(&state.db)
     .await?;
     let mut data = String::new();
 
-    roles.sort_by(|a, b| a.requirement.cmp(&b.requirement));
+    roles.sort_by_key(|role| (role.requirement, role.remove, role.id));
 
-    for role in roles {
+    for level in roles.chunk_by(|a, b| a.requirement == b.requirement) {
+        let (taken, given): (Vec<_>, Vec<_>) = level.iter().partition(|role| role.remove);
+        write!(data, "Level {}:", level[0].requirement)?;
+        if !given.is_empty() {
+            write!(
+                data,
+                " gives {}",
+                role_mentions(given.iter().map(|role| role.id))
+            )?;
+        }
+        if !taken.is_empty() {
+            let separator = if given.is_empty() { "" } else { "," };
+            write!(
+                data,
+                "{separator} takes away {}",
+                role_mentions(taken.iter().map(|role| role.id))
+            )?;
+        }
+        data.push('\n');
+    }
+
+    let mut rank_roles = query!(
+        "SELECT id, max_rank FROM rank_rewards WHERE guild = $1",
+        id_to_db(guild_id)
+    )
+    .fetch_all(&state.db)
+    .await?;
+    rank_roles.sort_by_key(|role| role.max_rank);
+
+    for role in rank_roles {
+        writeln!(
+            data,
+            "Rank reward <@&{}> for the top {} members",
+            role.id, role.max_rank
+        )?;
+    }
+
+    let mut prestige_roles = query!(
+        "SELECT id, tier FROM prestige_rewards WHERE guild = $1",
+        id_to_db(guild_id)
+    )
+    .fetch_all(&state.db)
+    .await?;
+    prestige_roles.sort_by_key(|role| (role.tier, role.id));
+
+    for role in prestige_roles {
         writeln!(
             data,
-            "Role reward <@&{}> at level {}",
-            role.id, role.requirement
+            "Prestige reward <@&{}> at prestige {}",
+            role.id, role.tier
         )?;
     }
     if data.is_empty() {
@@ -386,16 +629,368 @@ async fn process_rewards_list(
     Ok(data)
 }
 
+#[allow(clippy::unnecessary_wraps)]
+fn process_rewards_sync(
+    state: SlashState,
+    respondable: Respondable,
+    guild_id: Id<GuildMarker>,
+) -> Result<String, Error> {
+    state.clone().spawn(background_rewards_sync_wrapper(
+        state,
+        respondable,
+        guild_id,
+    ));
+    Ok("Syncing reward roles for every member with XP, check back soon!".to_string())
+}
+
+/// How many members to sync between progress reports
+const SYNC_PROGRESS_INTERVAL: usize = 50;
+
+#[derive(Default)]
+struct RewardsSyncSummary {
+    updated: usize,
+    unchanged: usize,
+    not_found: usize,
+    failed: usize,
+}
+
+async fn background_rewards_sync(
+    state: &SlashState,
+    report: &mut SyncReport,
+    guild_id: Id<GuildMarker>,
+) -> Result<XpdSlashResponse, Error> {
+    let start = Instant::now();
+
+    let rewards = GuildRewards::load(state, guild_id).await?;
+    if rewards.is_empty() {
+        return Ok(XpdSlashResponse::with_embed_text(
+            "This server has no role rewards to sync!",
+        ));
+    }
+
+    let members: Vec<SyncMember> = query!(
+        "SELECT id, xp, prestige FROM levels WHERE guild = $1",
+        id_to_db(guild_id)
+    )
+    .fetch_all(&state.db)
+    .await?
+    .into_iter()
+    .map(|row| (db_to_id(row.id), row.xp, row.prestige))
+    .collect();
+    let total = members.len();
+
+    let summary = sync_members(state, report, guild_id, &rewards, members).await;
+
+    let seconds = start.elapsed().as_secs_f64();
+    let mut message = format!("Synced reward roles for {total} members in {seconds:.2} seconds!\n");
+    summary.describe(&mut message)?;
+    Ok(XpdSlashResponse::with_embed_text(message))
+}
+
+/// A member's ID, XP, and prestige tier
+type SyncMember = (Id<UserMarker>, i64, i64);
+
+/// Where a reward sync reports its progress and results.
+///
+/// Interaction tokens expire after 15 minutes, which syncing a big server can outlast.
+/// So once there's progress to report, it goes in a message of our own in the channel
+/// the command was used in, and the results are edited into that message too.
+struct SyncReport {
+    respondable: Respondable,
+    message: Option<(Id<ChannelMarker>, Id<MessageMarker>)>,
+}
+
+impl SyncReport {
+    const fn new(respondable: Respondable) -> Self {
+        Self {
+            respondable,
+            message: None,
+        }
+    }
+
+    async fn progress(&mut self, state: &SlashState, text: String) {
+        let Some(channel) = self.respondable.channel() else {
+            return;
+        };
+        let embeds = [EmbedBuilder::new().description(text).build()];
+        if let Some((channel, message)) = self.message {
+            if let Err(source) = state
+                .client
+                .update_message(channel, message)
+                .embeds(Some(&embeds))
+                .await
+            {
+                warn!(?source, "Failed to report reward sync progress");
+            }
+            return;
+        }
+        match state.client.create_message(channel).embeds(&embeds).await {
+            Ok(response) => match response.model().await {
+                Ok(message) => self.message = Some((channel, message.id)),
+                Err(source) => warn!(?source, "Failed to read reward sync progress message"),
+            },
+            Err(source) => warn!(?source, "Failed to report reward sync progress"),
+        }
+    }
+
+    /// Send the results, in the progress message if there is one, or else as a followup.
+    async fn finish(self, state: &SlashState, response: XpdSlashResponse) {
+        let Some((channel, message)) = self.message else {
+            state
+                .send_followup(response, self.respondable.token())
+                .await;
+            return;
+        };
+        if let Err(source) = state
+            .client
+            .update_message(channel, message)
+            .embeds(Some(&response.embeds.unwrap_or_default()))
+            .await
+        {
+            warn!(?source, "Failed to report reward sync results");
+        }
+    }
+}
+
+/// Sync reward roles for each of `members`, reporting progress to `report`.
+async fn sync_members(
+    state: &SlashState,
+    report: &mut SyncReport,
+    guild_id: Id<GuildMarker>,
+    rewards: &GuildRewards,
+    members: Vec<SyncMember>,
+) -> RewardsSyncSummary {
+    let total = members.len();
+    let mut summary = RewardsSyncSummary::default();
+    let users = members.iter().map(|(user_id, _, _)| *user_id).collect();
+    let member_roles = match list_member_roles(state, guild_id, &users).await {
+        Ok(member_roles) => Some(member_roles),
+        Err(source) => {
+            debug!(
+                ?source,
+                ?guild_id,
+                "Could not list members, fetching them one at a time"
+            );
+            None
+        }
+    };
+    // one member at a time, so the HTTP client's ratelimiter can pace us
+    for (idx, (user_id, xp, prestige)) in members.into_iter().enumerate() {
+        let outcome = match &member_roles {
+            // members missing from the list have left
+            Some(member_roles) => match member_roles.get(&user_id) {
+                Some(roles) => {
+                    rewards
+                        .sync_member_roles(state, guild_id, user_id, roles, xp, prestige)
+                        .await
+                }
+                None => Ok(RoleSyncOutcome::NotFound),
+            },
+            None => {
+                rewards
+                    .sync_member(state, guild_id, user_id, xp, prestige)
+                    .await
+            }
+        };
+        match outcome {
+            Ok(RoleSyncOutcome::Updated) => summary.updated += 1,
+            Ok(RoleSyncOutcome::Unchanged) => summary.unchanged += 1,
+            Ok(RoleSyncOutcome::NotFound) => summary.not_found += 1,
+            Err(source) => {
+                warn!(?source, ?user_id, ?guild_id, "Failed to sync reward roles");
+                summary.failed += 1;
+            }
+        }
+
+        let done = idx + 1;
+        if done % SYNC_PROGRESS_INTERVAL == 0 && done < total {
+            let progress = format!("Syncing reward roles... {done}/{total} members done.");
+            report.progress(state, progress).await;
+        }
+    }
+    summary
+}
+
+impl RewardsSyncSummary {
+    fn describe(&self, message: &mut String) -> std::fmt::Result {
+        let Self {
+            updated,
+            unchanged,
+            not_found,
+            failed,
+        } = self;
+        write!(
+            message,
+            "Updated: {updated}\n\
+            Already correct: {unchanged}\n\
+            No longer in this server: {not_found}"
+        )?;
+        if *failed > 0 {
+            write!(
+                message,
+                "\nFailed: {failed}. Make sure I have the Manage Roles permission, \
+                and that my highest role is above all reward roles."
+            )?;
+        }
+        Ok(())
+    }
+}
+
+async fn background_rewards_sync_wrapper(
+    state: SlashState,
+    respondable: Respondable,
+    guild_id: Id<GuildMarker>,
+) {
+    let mut report = SyncReport::new(respondable);
+    let xsr = background_rewards_sync(&state, &mut report, guild_id)
+        .await
+        .unwrap_or_else(|source| {
+            error!(?source, "Failed to sync reward roles");
+            XpdSlashResponse::with_embed_text(format!("Failed to sync reward roles: {source}"))
+        })
+        .ephemeral(true);
+    report.finish(&state, xsr).await;
+}
+
+fn role_mentions(roles: impl Iterator<Item = i64>) -> String {
+    roles
+        .map(|role| format!("<@&{role}>"))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+async fn process_multipliers(
+    cmd: XpCommandMultipliers,
+    guild_id: Id<GuildMarker>,
+    state: SlashState,
+) -> Result<String, Error> {
+    match cmd {
+        XpCommandMultipliers::Add(add) => process_multipliers_add(add, state, guild_id).await,
+        XpCommandMultipliers::Remove(rm) => process_multipliers_rm(rm, state, guild_id).await,
+        XpCommandMultipliers::List(_list) => process_multipliers_list(state, guild_id).await,
+    }
+}
+
+async fn process_multipliers_add(
+    options: XpCommandMultipliersAdd,
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<String, Error> {
+    #[allow(clippy::cast_possible_truncation)]
+    let multiplier = options.multiplier as f32;
+    query!(
+        "INSERT INTO role_xp_multipliers (id, guild, multiplier) VALUES ($1, $2, $3) \
+            ON CONFLICT (id, guild) DO UPDATE SET multiplier = excluded.multiplier",
+        id_to_db(options.role.id),
+        id_to_db(guild_id),
+        multiplier
+    )
+    .execute(&state.db)
+    .await?;
+    state.invalidate_role_rules(guild_id).await;
+    if multiplier == 0.0 {
+        Ok(format!(
+            "Members with <@&{}> will no longer earn XP!",
+            options.role.id
+        ))
+    } else {
+        Ok(format!(
+            "Set XP multiplier for <@&{}> to {multiplier}x!",
+            options.role.id
+        ))
+    }
+}
+
+async fn process_multipliers_rm(
+    options: XpCommandMultipliersRemove,
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<String, Error> {
+    query!(
+        "DELETE FROM role_xp_multipliers WHERE id = $1 AND guild = $2",
+        id_to_db(options.role),
+        id_to_db(guild_id)
+    )
+    .execute(&state.db)
+    .await?;
+    state.invalidate_role_rules(guild_id).await;
+    Ok(format!("Removed XP multiplier for <@&{}>!", options.role))
+}
+
+async fn process_multipliers_list(
+    state: SlashState,
+    guild_id: Id<GuildMarker>,
+) -> Result<String, Error> {
+    let mut multipliers = query!(
+        "SELECT id, multiplier FROM role_xp_multipliers WHERE guild = $1",
+        id_to_db(guild_id)
+    )
+    .fetch_all(&state.db)
+    .await?;
+    multipliers.sort_by(|a, b| b.multiplier.total_cmp(&a.multiplier));
+
+    let mut data = String::new();
+    for multiplier in multipliers {
+        writeln!(data, "<@&{}>: {}x", multiplier.id, multiplier.multiplier)?;
+    }
+    if data.is_empty() {
+        data = "No role XP multipliers set for this server".to_string();
+    } else {
+        data.push_str(
+            "\nMembers with a 0x role earn no XP. Otherwise, only their highest multiplier counts.",
+        );
+    }
+    Ok(data)
+}
+
 async fn reset_guild_xp(
     guild_id: Id<GuildMarker>,
     confirmation: String,
+    respondable: Respondable,
     state: SlashState,
 ) -> Result<String, Error> {
     if confirmation != crate::cmd_defs::manage::CONFIRMATION_STRING {
         return Ok("Confirmation string did not match.".to_string());
     }
-    query!("DELETE FROM levels WHERE guild = $1", id_to_db(guild_id))
-        .execute(&state.db)
-        .await?;
-    Ok("Done. Thank you for using Experienced.".to_string())
+    let members: Vec<SyncMember> = query!(
+        "DELETE FROM levels WHERE guild = $1 RETURNING id",
+        id_to_db(guild_id)
+    )
+    .fetch_all(&state.db)
+    .await?
+    .into_iter()
+    .map(|row| (db_to_id(row.id), 0, 0))
+    .collect();
+    let rewards = GuildRewards::load(&state, guild_id).await?;
+    if rewards.is_empty() || members.is_empty() {
+        return Ok("Done. Thank you for using Experienced.".to_string());
+    }
+    let count = members.len();
+    state.clone().spawn(background_reset_sync_wrapper(
+        state,
+        respondable,
+        guild_id,
+        rewards,
+        members,
+    ));
+    Ok(format!(
+        "Done. Taking reward roles away from {count} members, check back soon!"
+    ))
+}
+
+async fn background_reset_sync_wrapper(
+    state: SlashState,
+    respondable: Respondable,
+    guild_id: Id<GuildMarker>,
+    rewards: GuildRewards,
+    members: Vec<SyncMember>,
+) {
+    let mut report = SyncReport::new(respondable);
+    let summary = sync_members(&state, &mut report, guild_id, &rewards, members).await;
+    let mut message = "Took away reward roles from members whose XP was reset.\n".to_string();
+    if let Err(source) = summary.describe(&mut message) {
+        error!(?source, "Failed to describe reward sync");
+    }
+    let xsr = XpdSlashResponse::with_embed_text(message).ephemeral(true);
+    report.finish(&state, xsr).await;
 }
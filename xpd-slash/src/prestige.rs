@@ -0,0 +1,55 @@
+use std::fmt::Write;
+
+use mee6::LevelCurve;
+use sqlx::query;
+use twilight_model::{
+    channel::message::AllowedMentions,
+    id::{
+        marker::{GuildMarker, UserMarker},
+        Id,
+    },
+};
+use xpd_common::id_to_db;
+
+use crate::{rewards::sync_after_edit, Error, SlashState, XpdSlashResponse};
+
+/// Reset a member's XP in exchange for the next prestige tier, if they've reached the
+/// guild's prestige level.
+pub async fn prestige(
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+    state: SlashState,
+) -> Result<XpdSlashResponse, Error> {
+    let config = state.get_guild_config(guild_id).await?;
+    let level = config.prestige_level().ok_or(Error::PrestigeDisabled)?;
+    let needed = u64::try_from(level)
+        .ok()
+        .and_then(|level| config.level_curve().xp_needed_for_level(level))
+        .and_then(|xp| i64::try_from(xp).ok())
+        .ok_or(Error::PrestigeLevelUnreachable(level))?;
+    // checking the XP in the same query means two prestiges at once can't both go through
+    let tier = query!(
+        "UPDATE levels SET xp = 0, prestige = prestige + 1 \
+            WHERE id = $1 AND guild = $2 AND xp >= $3 \
+            RETURNING prestige",
+        id_to_db(user_id),
+        id_to_db(guild_id),
+        needed
+    )
+    .fetch_optional(&state.db)
+    .await?
+    .ok_or(Error::NotReadyToPrestige(level))?
+    .prestige;
+
+    let mut message = format!("<@{user_id}> reset their XP and reached prestige {tier}!");
+    if config.prestige_xp_multiplier.is_some() {
+        write!(
+            message,
+            " They now earn {:.2}x XP.",
+            config.prestige_multiplier(tier)
+        )?;
+    }
+    message.push_str(sync_after_edit(&state, guild_id, user_id, 0, tier).await);
+    Ok(XpdSlashResponse::with_embed_text(message)
+        .allowed_mentions_o(Some(AllowedMentions::default())))
+}
@@ -0,0 +1,195 @@
+use std::collections::{HashMap, HashSet};
+
+use mee6::{Curve, LevelInfo};
+use sqlx::query;
+use twilight_model::id::{
+    marker::{GuildMarker, RoleMarker, UserMarker},
+    Id,
+};
+use xpd_common::{db_to_id, id_to_db, is_not_found, RewardRoleChanges, RoleReward};
+
+use crate::{Error, SlashState};
+
+/// Most members Discord will list at once
+const MEMBER_PAGE_SIZE: u16 = 1000;
+
+/// Everything needed to work out which reward roles a member of a guild should have.
+pub struct GuildRewards {
+    rewards: Vec<RoleReward>,
+    /// Rewards for prestige tiers, empty when prestige is turned off
+    prestige_rewards: Vec<RoleReward>,
+    one_at_a_time: bool,
+    curve: Curve,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoleSyncOutcome {
+    Updated,
+    Unchanged,
+    NotFound,
+}
+
+impl GuildRewards {
+    pub async fn load(state: &SlashState, guild_id: Id<GuildMarker>) -> Result<Self, Error> {
+        let rewards = query!(
+            "SELECT id, requirement, remove FROM role_rewards WHERE guild = $1",
+            id_to_db(guild_id)
+        )
+        .fetch_all(&state.db)
+        .await?
+        .into_iter()
+        .map(|row| RoleReward {
+            id: db_to_id(row.id),
+            requirement: row.requirement,
+            remove: row.remove,
+        })
+        .collect();
+        let config = state.get_guild_config(guild_id).await?;
+        let prestige_rewards = if config.prestige_level().is_some() {
+            query!(
+                "SELECT id, tier FROM prestige_rewards WHERE guild = $1",
+                id_to_db(guild_id)
+            )
+            .fetch_all(&state.db)
+            .await?
+            .into_iter()
+            .map(|row| RoleReward {
+                id: db_to_id(row.id),
+                requirement: row.tier,
+                remove: false,
+            })
+            .collect()
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            rewards,
+            prestige_rewards,
+            one_at_a_time: config.one_at_a_time.unwrap_or(false),
+            curve: config.level_curve.unwrap_or_default(),
+        })
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.rewards.is_empty() && self.prestige_rewards.is_empty()
+    }
+
+    /// Add and remove reward roles so that they match a member with `xp` experience
+    /// at prestige tier `prestige`.
+    pub async fn sync_member(
+        &self,
+        state: &SlashState,
+        guild_id: Id<GuildMarker>,
+        user_id: Id<UserMarker>,
+        xp: i64,
+        prestige: i64,
+    ) -> Result<RoleSyncOutcome, Error> {
+        if self.is_empty() {
+            return Ok(RoleSyncOutcome::Unchanged);
+        }
+        let member = match state.client.guild_member(guild_id, user_id).await {
+            Ok(response) => response.model().await?,
+            Err(source) if is_not_found(&source) => return Ok(RoleSyncOutcome::NotFound),
+            Err(source) => return Err(source.into()),
+        };
+        self.sync_member_roles(state, guild_id, user_id, &member.roles, xp, prestige)
+            .await
+    }
+
+    /// Like [`Self::sync_member`], for a member whose current `roles` are already known.
+    /// Nothing is sent to Discord unless their roles need to change.
+    pub async fn sync_member_roles(
+        &self,
+        state: &SlashState,
+        guild_id: Id<GuildMarker>,
+        user_id: Id<UserMarker>,
+        roles: &[Id<RoleMarker>],
+        xp: i64,
+        prestige: i64,
+    ) -> Result<RoleSyncOutcome, Error> {
+        let level = LevelInfo::new(&self.curve, xp.try_into().unwrap_or(0)).level();
+        let changes = RewardRoleChanges::new(
+            roles,
+            &self.rewards,
+            level.try_into().unwrap_or(i64::MAX),
+            self.one_at_a_time,
+        )
+        .merge(RewardRoleChanges::new(
+            roles,
+            &self.prestige_rewards,
+            prestige,
+            false,
+        ));
+        if changes.is_empty() {
+            return Ok(RoleSyncOutcome::Unchanged);
+        }
+        debug!(user = ?user_id, add = ?changes.add, remove = ?changes.remove, "Syncing reward roles");
+        match state
+            .client
+            .update_guild_member(guild_id, user_id)
+            .roles(&changes.apply(roles))
+            .await
+        {
+            Ok(_) => Ok(RoleSyncOutcome::Updated),
+            // they may have left since their roles were listed
+            Err(source) if is_not_found(&source) => Ok(RoleSyncOutcome::NotFound),
+            Err(source) => Err(source.into()),
+        }
+    }
+}
+
+/// The roles of each of `users` who is still a member of the guild, listed a page at a time
+/// instead of fetching every member on their own.
+/// Discord only lists members to bots with the server members intent.
+pub async fn list_member_roles(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    users: &HashSet<Id<UserMarker>>,
+) -> Result<HashMap<Id<UserMarker>, Vec<Id<RoleMarker>>>, Error> {
+    let mut roles = HashMap::with_capacity(users.len());
+    let mut after = None;
+    loop {
+        let mut request = state.client.guild_members(guild_id).limit(MEMBER_PAGE_SIZE);
+        if let Some(after) = after {
+            request = request.after(after);
+        }
+        let page = request.await?.models().await?;
+        after = page.last().map(|member| member.user.id);
+        let last_page = page.len() < usize::from(MEMBER_PAGE_SIZE);
+        roles.extend(
+            page.into_iter()
+                .filter(|member| users.contains(&member.user.id))
+                .map(|member| (member.user.id, member.roles)),
+        );
+        if last_page {
+            return Ok(roles);
+        }
+    }
+}
+
+/// Sync a single member's reward roles after their XP or prestige tier changed,
+/// returning a note to append to the command's response.
+pub async fn sync_after_edit(
+    state: &SlashState,
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+    xp: i64,
+    prestige: i64,
+) -> &'static str {
+    let outcome = match GuildRewards::load(state, guild_id).await {
+        Ok(rewards) => {
+            rewards
+                .sync_member(state, guild_id, user_id, xp, prestige)
+                .await
+        }
+        Err(source) => Err(source),
+    };
+    match outcome {
+        Ok(RoleSyncOutcome::Updated) => " Their reward roles were updated to match.",
+        Ok(RoleSyncOutcome::Unchanged | RoleSyncOutcome::NotFound) => "",
+        Err(source) => {
+            warn!(?source, ?guild_id, ?user_id, "Could not sync reward roles");
+            " I couldn't update their reward roles, so please check my permissions."
+        }
+    }
+}